@@ -0,0 +1,13 @@
+-- Create the download_queue table holding the downloads that haven't finished
+CREATE TABLE IF NOT EXISTS download_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    container TEXT NOT NULL,
+    width TEXT NOT NULL,
+    height TEXT NOT NULL,
+    fps TEXT NOT NULL,
+    url TEXT,
+    destination TEXT NOT NULL,
+    paused BOOLEAN NOT NULL,
+    video_info_id INTEGER NOT NULL,
+    FOREIGN KEY (video_info_id) REFERENCES video_info (id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Record how far interrupted downloads got so they can be resumed
+ALTER TABLE download_queue ADD COLUMN resume_offset INTEGER NOT NULL DEFAULT 0;
\ No newline at end of file
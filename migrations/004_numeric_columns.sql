@@ -0,0 +1,47 @@
+-- Store durations, dimensions and frame rates as numbers instead of TEXT.
+-- SQLite can't change the type of a column, so the video_format and
+-- download_queue tables are recreated. video_info is referenced by other
+-- tables and is changed in place instead, recreating it would cascade
+-- the deletion of its old rows.
+ALTER TABLE video_info ADD COLUMN duration INTEGER NOT NULL DEFAULT 0;
+UPDATE video_info SET duration = CAST(duration_seconds AS INTEGER);
+ALTER TABLE video_info DROP COLUMN duration_seconds;
+ALTER TABLE video_info RENAME COLUMN duration TO duration_seconds;
+
+CREATE TABLE video_format_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    container TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    fps REAL NOT NULL,
+    video_info_id INTEGER NOT NULL,
+    FOREIGN KEY (video_info_id) REFERENCES video_info (id) ON DELETE CASCADE
+);
+INSERT INTO video_format_new (id, container, width, height, fps, video_info_id)
+    SELECT id, container, CAST(width AS INTEGER), CAST(height AS INTEGER),
+        CAST(fps AS REAL), video_info_id
+    FROM video_format;
+DROP TABLE video_format;
+ALTER TABLE video_format_new RENAME TO video_format;
+
+CREATE TABLE download_queue_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    container TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    fps REAL NOT NULL,
+    url TEXT,
+    destination TEXT NOT NULL,
+    paused BOOLEAN NOT NULL,
+    resume_offset INTEGER NOT NULL DEFAULT 0,
+    video_info_id INTEGER NOT NULL,
+    FOREIGN KEY (video_info_id) REFERENCES video_info (id) ON DELETE CASCADE
+);
+INSERT INTO download_queue_new
+        (id, container, width, height, fps, url,
+            destination, paused, resume_offset, video_info_id)
+    SELECT id, container, CAST(width AS INTEGER), CAST(height AS INTEGER),
+        CAST(fps AS REAL), url, destination, paused, resume_offset, video_info_id
+    FROM download_queue;
+DROP TABLE download_queue;
+ALTER TABLE download_queue_new RENAME TO download_queue;
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- Add the codecs, bitrate, size and id of the formats
+ALTER TABLE video_format ADD COLUMN format_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE video_format ADD COLUMN vcodec TEXT;
+ALTER TABLE video_format ADD COLUMN acodec TEXT;
+ALTER TABLE video_format ADD COLUMN bitrate_kbps INTEGER;
+ALTER TABLE video_format ADD COLUMN filesize INTEGER;
+ALTER TABLE video_format ADD COLUMN hdr BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE video_format ADD COLUMN is_audio_only BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE download_queue ADD COLUMN format_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE download_queue ADD COLUMN vcodec TEXT;
+ALTER TABLE download_queue ADD COLUMN acodec TEXT;
+ALTER TABLE download_queue ADD COLUMN bitrate_kbps INTEGER;
+ALTER TABLE download_queue ADD COLUMN filesize INTEGER;
+ALTER TABLE download_queue ADD COLUMN hdr BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE download_queue ADD COLUMN is_audio_only BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
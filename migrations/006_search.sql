@@ -0,0 +1,28 @@
+-- Index the titles and authors of the videos for full-text search
+CREATE VIRTUAL TABLE video_search USING fts5(
+    title,
+    author,
+    content = 'video_info',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO video_search (video_search) VALUES ('rebuild');
+
+-- Keep the index in sync with video_info
+CREATE TRIGGER video_search_insert AFTER INSERT ON video_info BEGIN
+    INSERT INTO video_search (rowid, title, author)
+        VALUES (new.id, new.title, new.author);
+END;
+
+CREATE TRIGGER video_search_delete AFTER DELETE ON video_info BEGIN
+    INSERT INTO video_search (video_search, rowid, title, author)
+        VALUES ('delete', old.id, old.title, old.author);
+END;
+
+CREATE TRIGGER video_search_update AFTER UPDATE OF title, author ON video_info BEGIN
+    INSERT INTO video_search (video_search, rowid, title, author)
+        VALUES ('delete', old.id, old.title, old.author);
+    INSERT INTO video_search (rowid, title, author)
+        VALUES (new.id, new.title, new.author);
+END;
\ No newline at end of file
@@ -0,0 +1,13 @@
+-- Record when each video was added, in seconds since the Unix epoch.
+-- The videos already there are dated at the time of the migration.
+ALTER TABLE video_info ADD COLUMN added_at INTEGER NOT NULL DEFAULT 0;
+UPDATE video_info SET added_at = CAST(strftime('%s', 'now') AS INTEGER);
+
+CREATE INDEX video_info_added_at ON video_info (added_at, id);
+
+-- Record when, where and in which format each video was downloaded
+ALTER TABLE video_info ADD COLUMN downloaded_at INTEGER;
+ALTER TABLE video_info ADD COLUMN output_path TEXT;
+ALTER TABLE video_info ADD COLUMN file_size INTEGER;
+ALTER TABLE video_info ADD COLUMN chosen_format_id TEXT;
+ALTER TABLE video_info ADD COLUMN status TEXT NOT NULL DEFAULT 'added';
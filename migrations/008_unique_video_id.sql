@@ -0,0 +1,61 @@
+-- Merge the videos added more than once into the first one, refreshed with
+-- the metadata and formats of the last one, then forbid new duplicates
+CREATE TEMPORARY TABLE merged AS
+    SELECT video_id, MIN(id) AS kept_id, MAX(id) AS latest_id
+    FROM video_info
+    GROUP BY video_id
+    HAVING COUNT(*) > 1;
+
+UPDATE video_info
+SET (title, author, duration_seconds, thumbnail, audio_available) = (
+    SELECT latest.title, latest.author, latest.duration_seconds,
+        latest.thumbnail, latest.audio_available
+    FROM merged
+    JOIN video_info AS latest ON latest.id = merged.latest_id
+    WHERE merged.kept_id = video_info.id
+)
+WHERE id IN (SELECT kept_id FROM merged);
+
+-- Keep the most recent download of the video, if any
+UPDATE video_info
+SET (downloaded_at, output_path, file_size, chosen_format_id, status) = (
+    SELECT downloaded.downloaded_at, downloaded.output_path, downloaded.file_size,
+        downloaded.chosen_format_id, downloaded.status
+    FROM video_info AS downloaded
+    WHERE downloaded.video_id = video_info.video_id
+        AND downloaded.status = 'downloaded'
+    ORDER BY downloaded.downloaded_at DESC, downloaded.id DESC
+    LIMIT 1
+)
+WHERE id IN (SELECT kept_id FROM merged)
+    AND EXISTS (
+        SELECT 1 FROM video_info AS downloaded
+        WHERE downloaded.video_id = video_info.video_id
+            AND downloaded.status = 'downloaded'
+    );
+
+DELETE FROM video_format WHERE video_info_id IN (SELECT kept_id FROM merged);
+UPDATE video_format
+SET video_info_id = (
+    SELECT kept_id FROM merged WHERE merged.latest_id = video_format.video_info_id
+)
+WHERE video_info_id IN (SELECT latest_id FROM merged);
+
+UPDATE download_queue
+SET video_info_id = (
+    SELECT kept_id FROM merged
+    JOIN video_info ON video_info.video_id = merged.video_id
+    WHERE video_info.id = download_queue.video_info_id
+)
+WHERE video_info_id IN (
+    SELECT video_info.id FROM video_info JOIN merged USING (video_id)
+);
+
+-- Deleting the duplicates cascades to what is left of their formats
+DELETE FROM video_info
+WHERE id NOT IN (SELECT kept_id FROM merged)
+    AND video_id IN (SELECT video_id FROM merged);
+
+DROP TABLE merged;
+
+CREATE UNIQUE INDEX video_info_video_id ON video_info (video_id);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Deleted videos are moved to the trash first, in seconds since the Unix epoch
+ALTER TABLE video_info ADD COLUMN deleted_at INTEGER;
+
+CREATE INDEX video_info_deleted_at ON video_info (deleted_at);
\ No newline at end of file
@@ -1,82 +1,191 @@
 //! A database is used to store the history of downloaded videos.
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use const_format::formatcp;
-pub use sqlx::Result as sqlxResult;
 use sqlx::{
-    migrate::MigrateError,
-    sqlite::{SqliteConnectOptions, SqliteRow},
+    sqlite::{
+        SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow, SqliteSynchronous,
+    },
     *,
 };
 
-use crate::video::{ManagedVideo, VideoInfo};
+use crate::{
+    system_trash,
+    video::{DownloadRecord, DownloadStatus, ManagedVideo, VideoFormat, VideoInfo},
+    Result,
+};
+
+pub mod backup;
+pub mod export;
+pub mod history;
+pub mod import;
+pub mod location;
+pub mod stats;
 
 /// Creates a connection to a local SQLite database and offers CRUD operations.
 pub struct Database<DB: sqlx::database::Database> {
-    pool: Pool<DB>,
+    pub(crate) pool: Pool<DB>,
+}
+
+/// Cloning is cheap, the clones share the same underlying connection pool.
+impl<DB: sqlx::database::Database> Clone for Database<DB> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+/// How the connections to the database file are set up.
+///
+/// The file is always in WAL mode so that reading doesn't wait for writing,
+/// and it's only synced to disk at checkpoints, which can't corrupt it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolOptions {
+    /// How many connections are opened at most
+    pub max_connections: u32,
+    /// How long a write waits for another to finish before failing
+    pub busy_timeout: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 8,
+            busy_timeout: Duration::from_secs(10),
+        }
+    }
 }
 
 impl Database<Sqlite> {
     /// Initialize the database reading from the SQLite database file
-    /// supplied by [get_file_path](Self::get_file_path).
+    /// [located](Self::locate) without a command line flag.
     ///
     /// If the file does not exist, it will be created.
     ///
-    /// See also [init_with_filename](Self::init_with_filename).
-    pub async fn init() -> sqlxResult<Self> {
-        Self::init_with_filename(Self::get_file_path()?).await
+    /// See also [init_at](Self::init_at) and
+    /// [init_with_filename](Self::init_with_filename).
+    pub async fn init() -> Result<Self> {
+        Self::init_at(&Self::locate(None)?, PoolOptions::default()).await
     }
 
     /// Initialize the database reading from the SQLite database file
     /// at the `path`. [init](Self::init) is most likely what you want to use
     /// for default behavior.
     ///
-    /// If the file does not exist, it will be created.
-    pub async fn init_with_filename(path: impl AsRef<Path>) -> sqlxResult<Self> {
+    /// If the file does not exist, it will be created. If it has to be
+    /// migrated, it's [backed up](Self::backups_dir) first, unless the backup
+    /// fails, which is only logged.
+    pub async fn init_with_filename(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init_with_options(path, PoolOptions::default()).await
+    }
+
+    /// Same as [init_with_filename](Self::init_with_filename), with the
+    /// connections set up as `options`.
+    pub async fn init_with_options(path: impl AsRef<Path>, options: PoolOptions) -> Result<Self> {
         let opts = SqliteConnectOptions::new()
-            .filename(path)
-            .create_if_missing(true);
-        let pool = SqlitePool::connect_with(opts).await?;
+            .filename(path.as_ref())
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(options.busy_timeout);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(options.max_connections)
+            .connect_with(opts)
+            .await?;
 
         let db = Database { pool };
+        if db.needs_migrations().await? {
+            // A healthy history is still opened without its backup
+            if let Err(e) = db.backup_rotating(path.as_ref()).await {
+                tracing::warn!("failed to back up the history database before migrating: {e}");
+            }
+        }
         db.apply_migrations().await?;
 
         Ok(db)
     }
 
-    /// Get the default path of where the `.db` file should be located.
-    /// The path is intended to be in the same directory as the executable.
+    /// Get the path of where the `.db` file is located when there is no
+    /// command line flag. See [locate](Self::locate).
     ///
     /// # Errors
-    /// May fail with an [std::io::Error] when getting the path to the
-    /// running executable because it's used to derive the path to the `.db` file.
+    /// May fail with an [std::io::Error] when reading the settings file or
+    /// if there is no home directory to derive the path to the `.db` file from.
     pub fn get_file_path() -> std::result::Result<PathBuf, std::io::Error> {
-        const FILE_NAME: &str = "history.db";
-
-        let mut path = std::env::current_exe()?;
-        path.pop();
-        path.push(FILE_NAME);
-
-        Ok(path)
+        Ok(Self::locate(None)?.path)
     }
 
     /// Applies SQL migrations to the database.
     /// This method is already called by the init functions so it is
     /// unlikely this needs to be called again.
-    pub async fn apply_migrations(&self) -> sqlxResult<(), MigrateError> {
-        migrate!().run(&self.pool).await
+    pub async fn apply_migrations(&self) -> Result<()> {
+        Ok(migrate!().run(&self.pool).await?)
     }
 
-    /// Close the connection to the database
+    /// Close the connection to the database, with everything written to the
+    /// database file itself so that it can be copied or replaced on its own.
     pub async fn close(self) {
+        // The last connection to close checkpoints the WAL too, but not when
+        // another one is closing at the same time
+        if let Err(e) = query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await
+        {
+            tracing::warn!("failed to checkpoint the history database: {e}");
+        }
         self.pool.close().await;
     }
 
-    async fn get_transaction(&self) -> sqlxResult<Transaction<Sqlite>> {
+    async fn get_transaction(&self) -> sqlx::Result<Transaction<'_, Sqlite>> {
         self.pool.begin().await
     }
 }
 
+/// How many times [retry_busy] runs a write transaction again.
+const MAX_BUSY_RETRIES: u32 = 5;
+
+/// Run the `write` transaction again when it fails because the database is
+/// busy.
+///
+/// sqlx begins the transactions as `DEFERRED`, and preparing a write to
+/// `video_info` reads the configuration of the search index, so a transaction
+/// starts as a reader. Upgrading it to a writer fails right away instead of
+/// waiting for the [busy timeout](PoolOptions::busy_timeout) when another
+/// connection has written in between, and only beginning again can fix that.
+async fn retry_busy<T, F, Fut>(mut write: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match write().await {
+            Err(e) if is_busy(&e) && retries < MAX_BUSY_RETRIES => {
+                retries += 1;
+                tokio::time::sleep(Duration::from_millis(10 << retries)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether `e` is `SQLITE_BUSY`, or one of its extended result codes.
+fn is_busy(e: &crate::Error) -> bool {
+    const SQLITE_BUSY: i32 = 5;
+    match e {
+        crate::Error::Database(sqlx::Error::Database(e)) => e
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| code & 0xff == SQLITE_BUSY),
+        _ => false,
+    }
+}
+
 const ID: &str = "id";
 
 const VIDEO_INFO: &str = "VIDEO_INFO";
@@ -86,53 +195,89 @@ const AUTHOR: &str = "author";
 const DURATION_SECONDS: &str = "duration_seconds";
 const THUMBNAIL: &str = "thumbnail";
 const AUDIO_AVAILABLE: &str = "audio_available";
+const ADDED_AT: &str = "added_at";
+const DOWNLOADED_AT: &str = "downloaded_at";
+const OUTPUT_PATH: &str = "output_path";
+const FILE_SIZE: &str = "file_size";
+const CHOSEN_FORMAT_ID: &str = "chosen_format_id";
+const STATUS: &str = "status";
+const DELETED_AT: &str = "deleted_at";
+/// The columns of a [DownloadRecord]
+const RECORD_COLUMNS: &str = formatcp!(
+    "{ADDED_AT}, {DOWNLOADED_AT}, {OUTPUT_PATH}, {FILE_SIZE}, {CHOSEN_FORMAT_ID}, {STATUS},
+        {DELETED_AT}"
+);
+
+const VIDEO_SEARCH: &str = "video_search";
 
 const VIDEO_FORMAT: &str = "video_format";
 const CONTAINER: &str = "container";
 const WIDTH: &str = "width";
 const HEIGHT: &str = "height";
 const FPS: &str = "fps";
+const FORMAT_ID: &str = "format_id";
+const VCODEC: &str = "vcodec";
+const ACODEC: &str = "acodec";
+const BITRATE_KBPS: &str = "bitrate_kbps";
+const FILESIZE: &str = "filesize";
+const HDR: &str = "hdr";
+const IS_AUDIO_ONLY: &str = "is_audio_only";
 const VIDEO_INFO_ID: &str = "video_info_id";
 
-const QUERY_INSERT_INFO: &str = formatcp!(
+const INSERT_INFO: &str = formatcp!(
     "INSERT INTO {VIDEO_INFO}
         ({VIDEO_ID}, {TITLE}, {AUTHOR},
-            {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE})
+            {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {ADDED_AT})
      VALUES
         ($1, $2, $3,
-            $4, $5, $6)
-     RETURNING
-        {ID}
-    "
+            $4, $5, $6, CAST(strftime('%s', 'now') AS INTEGER))"
 );
+const QUERY_INSERT_INFO: &str = formatcp!("{INSERT_INFO} RETURNING {ID}");
+/// Like [QUERY_INSERT_INFO] but returning no row if the video is already there
+const QUERY_INSERT_NEW_INFO: &str =
+    formatcp!("{INSERT_INFO} ON CONFLICT ({VIDEO_ID}) DO NOTHING RETURNING {ID}");
 const QUERY_INSERT_FORMAT: &str = formatcp!(
     "INSERT INTO {VIDEO_FORMAT}
-        ({CONTAINER}, {WIDTH}, {HEIGHT}, {FPS}, {VIDEO_INFO_ID})
+        ({FORMAT_ID}, {CONTAINER}, {WIDTH}, {HEIGHT}, {FPS}, {VCODEC}, {ACODEC},
+            {BITRATE_KBPS}, {FILESIZE}, {HDR}, {IS_AUDIO_ONLY}, {VIDEO_INFO_ID})
      VALUES
-        ($1, $2, $3, $4, $5)
+        ($1, $2, $3, $4, $5, $6, $7,
+            $8, $9, $10, $11, $12)
     "
 );
 
 const QUERY_FETCH_ONE_INFO: &str = formatcp!(
-    "SELECT {VIDEO_ID}, {TITLE}, {AUTHOR},
-        {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}
+    "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
+        {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
      FROM {VIDEO_INFO}
      WHERE {ID} = $1
     "
 );
 
 const QUERY_FETCH_ONE_FORMATS: &str = formatcp!(
-    "SELECT {CONTAINER}, {WIDTH}, {HEIGHT}, {FPS}, {VIDEO_INFO_ID}
+    "SELECT {FORMAT_ID}, {CONTAINER}, {WIDTH}, {HEIGHT}, {FPS}, {VCODEC}, {ACODEC},
+        {BITRATE_KBPS}, {FILESIZE}, {HDR}, {IS_AUDIO_ONLY}, {VIDEO_INFO_ID}
      FROM {VIDEO_FORMAT}
      WHERE {VIDEO_INFO_ID} = $1
     "
 );
 
+/// The formats of the videos whose row ids are in the JSON array `$1`,
+/// in the order they were inserted
+const QUERY_FETCH_MANY_FORMATS: &str = formatcp!(
+    "SELECT {FORMAT_ID}, {CONTAINER}, {WIDTH}, {HEIGHT}, {FPS}, {VCODEC}, {ACODEC},
+        {BITRATE_KBPS}, {FILESIZE}, {HDR}, {IS_AUDIO_ONLY}, {VIDEO_INFO_ID}
+     FROM {VIDEO_FORMAT}
+     WHERE {VIDEO_INFO_ID} IN (SELECT value FROM json_each($1))
+     ORDER BY {VIDEO_INFO_ID}, {ID}
+    "
+);
+
 const QUERY_FETCH_CHUNK_INFO_GEQ: &str = formatcp!(
     "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
-        {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}
+        {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
      FROM {VIDEO_INFO}
-     WHERE {ID} >= $1
+     WHERE {ID} >= $1 AND {DELETED_AT} IS NULL
      ORDER BY {ID} ASC
      LIMIT $2
     "
@@ -140,32 +285,145 @@ const QUERY_FETCH_CHUNK_INFO_GEQ: &str = formatcp!(
 
 const QUERY_FETCH_CHUNK_INFO_LEQ: &str = formatcp!(
     "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
-        {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}
+        {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
      FROM {VIDEO_INFO}
-     WHERE {ID} <= $1
+     WHERE {ID} <= $1 AND {DELETED_AT} IS NULL
      ORDER BY {ID} DESC
      LIMIT $2
     "
 );
 
-struct IdAndInfo(i32, VideoInfo);
+impl FromRow<'_, SqliteRow> for VideoInfo {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            video_id: row.try_get(VIDEO_ID)?,
+            title: row.try_get(TITLE)?,
+            author: row.try_get(AUTHOR)?,
+            duration: Duration::from_secs(row.try_get::<i64, _>(DURATION_SECONDS)?.max(0) as u64),
+            thumbnail: row.try_get(THUMBNAIL)?,
+            video_formats: Vec::default(),
+            audio_available: row.try_get(AUDIO_AVAILABLE)?,
+        })
+    }
+}
+
+/// The [url](VideoFormat::url) is left empty, it's only stored along the
+/// queued downloads.
+impl FromRow<'_, SqliteRow> for VideoFormat {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            format_id: row.try_get(FORMAT_ID)?,
+            container: row.try_get(CONTAINER)?,
+            width: row.try_get(WIDTH)?,
+            height: row.try_get(HEIGHT)?,
+            fps: row.try_get(FPS)?,
+            vcodec: row.try_get(VCODEC)?,
+            acodec: row.try_get(ACODEC)?,
+            bitrate_kbps: row.try_get(BITRATE_KBPS)?,
+            filesize: row
+                .try_get::<Option<i64>, _>(FILESIZE)?
+                .map(|filesize| filesize as u64),
+            hdr: row.try_get(HDR)?,
+            is_audio_only: row.try_get(IS_AUDIO_ONLY)?,
+            url: None,
+        })
+    }
+}
+
+impl FromRow<'_, SqliteRow> for DownloadRecord {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            added_at: from_unix_seconds(row.try_get(ADDED_AT)?),
+            downloaded_at: row
+                .try_get::<Option<i64>, _>(DOWNLOADED_AT)?
+                .map(from_unix_seconds),
+            output_path: row
+                .try_get::<Option<String>, _>(OUTPUT_PATH)?
+                .map(PathBuf::from),
+            file_size: row
+                .try_get::<Option<i64>, _>(FILE_SIZE)?
+                .map(|file_size| file_size as u64),
+            chosen_format_id: row.try_get(CHOSEN_FORMAT_ID)?,
+            status: row.try_get(STATUS)?,
+            deleted_at: row
+                .try_get::<Option<i64>, _>(DELETED_AT)?
+                .map(from_unix_seconds),
+        })
+    }
+}
+
+struct InfoIdAndFormat(i32, VideoFormat);
+impl FromRow<'_, SqliteRow> for InfoIdAndFormat {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self(
+            row.try_get(VIDEO_INFO_ID)?,
+            VideoFormat::from_row(row)?,
+        ))
+    }
+}
+
+struct IdAndInfo(i32, VideoInfo, DownloadRecord);
 impl FromRow<'_, SqliteRow> for IdAndInfo {
     fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
         Ok(Self(
             row.try_get(ID)?,
-            VideoInfo {
-                video_id: row.try_get(VIDEO_ID)?,
-                title: row.try_get(TITLE)?,
-                author: row.try_get(AUTHOR)?,
-                duration_seconds: row.try_get(DURATION_SECONDS)?,
-                thumbnail: row.try_get(THUMBNAIL)?,
-                video_formats: Vec::default(),
-                audio_available: row.try_get(AUDIO_AVAILABLE)?,
-            },
+            VideoInfo::from_row(row)?,
+            DownloadRecord::from_row(row)?,
         ))
     }
 }
 
+/// Seconds since the Unix epoch, negative before it.
+fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Inverse of [unix_seconds], clamped to the Unix epoch.
+fn from_unix_seconds(seconds: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64)
+}
+
+/// Turn what the user typed into an FTS5 query matching every word as
+/// a prefix, so that it can't be a syntax error. `None` if there are no words.
+fn to_fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Insert the `video_formats` of the video with the matching row `id`.
+async fn insert_formats(
+    transaction: &mut Transaction<'_, Sqlite>,
+    id: i32,
+    video_formats: &[VideoFormat],
+) -> Result<()> {
+    for video_format in video_formats {
+        query(QUERY_INSERT_FORMAT)
+            .bind(&video_format.format_id)
+            .bind(&video_format.container)
+            .bind(video_format.width)
+            .bind(video_format.height)
+            .bind(video_format.fps)
+            .bind(&video_format.vcodec)
+            .bind(&video_format.acodec)
+            .bind(video_format.bitrate_kbps)
+            .bind(video_format.filesize.map(|filesize| filesize as i64))
+            .bind(video_format.hdr)
+            .bind(video_format.is_audio_only)
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?;
+    }
+
+    Ok(())
+}
+
 /// Used to specify the ordering of results from the fetch chunk methods.
 /// # See also
 /// - [`fetch_chunk_of`](Database::fetch_chunk_of)
@@ -182,10 +440,103 @@ pub enum FetchOrd {
     LEQandDESC,
 }
 
+/// What [insert_bulk_video_info_with](Database::insert_bulk_video_info_with)
+/// does with the videos already in the history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnExisting {
+    /// Fail without inserting any video
+    Fail,
+    /// Leave them as they are, except that those in the trash are taken out
+    /// of it
+    SkipOrRestore,
+}
+
+/// What [insert_bulk_video_info_with](Database::insert_bulk_video_info_with)
+/// did with a video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inserted {
+    /// Inserted with the contained row id
+    New(i32),
+    /// Taken out of the trash, with the contained row id
+    Restored(i32),
+    /// Already in the history
+    Skipped,
+}
+
+/// What [delete_video_info](Database::delete_video_info) does with the file
+/// the video was downloaded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeleteMode {
+    /// Only delete the video from the history, the file stays where it is
+    #[default]
+    RowOnly,
+    /// Delete the file for good as well
+    RowAndFile,
+    /// Move the file to the trash of the desktop, see [system_trash]
+    FileToSystemTrash,
+}
+
+/// What [delete_video_info](Database::delete_video_info) did.
+#[derive(Debug)]
+pub struct DeleteReport {
+    pub rows_deleted: u64,
+    /// Where the video was downloaded to, if it was
+    pub output_path: Option<PathBuf>,
+    /// Where the file was moved to by [DeleteMode::FileToSystemTrash]
+    pub trashed_to: Option<PathBuf>,
+    /// Why the file couldn't be removed, while the row was deleted anyway
+    pub file_error: Option<std::io::Error>,
+}
+
+impl DeleteReport {
+    /// Whether the row was deleted but not the file that was asked to be.
+    pub fn is_partial(&self) -> bool {
+        self.file_error.is_some()
+    }
+}
+
+#[cfg(test)]
+tokio::task_local! {
+    /// How many times [with_formats](Database::with_formats) queried the
+    /// formats, counted by the tests running in its scope.
+    static FORMAT_QUERIES: std::cell::Cell<usize>;
+}
+
 impl Database<Sqlite> {
+    /// Attach their formats to the videos of a chunk, fetched all at once.
+    async fn with_formats(&self, id_and_infos: Vec<IdAndInfo>) -> Result<Vec<ManagedVideo>> {
+        if id_and_infos.is_empty() {
+            return Ok(Vec::new());
+        }
+        #[cfg(test)]
+        let _ = FORMAT_QUERIES.try_with(|count| count.set(count.get() + 1));
+
+        let ids: Vec<i32> = id_and_infos.iter().map(|IdAndInfo(id, ..)| *id).collect();
+        let formats: Vec<InfoIdAndFormat> = query_as(QUERY_FETCH_MANY_FORMATS)
+            .bind(serde_json::to_string(&ids).expect("ids serialize to JSON"))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut formats_by_id: HashMap<i32, Vec<VideoFormat>> = HashMap::new();
+        for InfoIdAndFormat(id, format) in formats {
+            formats_by_id.entry(id).or_default().push(format);
+        }
+
+        Ok(id_and_infos
+            .into_iter()
+            .map(|IdAndInfo(id, mut video_info, record)| {
+                video_info.video_formats = formats_by_id.remove(&id).unwrap_or_default();
+                ManagedVideo::new(id, video_info).with_record(record)
+            })
+            .collect())
+    }
+
     /// Fetch the [ManagedVideo] with matching `id`.
-    pub async fn fetch_one(&self, id: i32) -> sqlxResult<ManagedVideo> {
-        let mut video_info: VideoInfo = query_as(QUERY_FETCH_ONE_INFO)
+    ///
+    /// # Errors
+    /// Fails with [Error::NotFound](crate::Error::NotFound) if there is none.
+    pub async fn fetch_one(&self, id: i32) -> Result<ManagedVideo> {
+        let IdAndInfo(id, mut video_info, record) = query_as(QUERY_FETCH_ONE_INFO)
             .bind(id)
             .fetch_one(&self.pool)
             .await?;
@@ -195,7 +546,7 @@ impl Database<Sqlite> {
             .fetch_all(&self.pool)
             .await?;
 
-        Ok(ManagedVideo::new(id, video_info))
+        Ok(ManagedVideo::new(id, video_info).with_record(record))
     }
 
     /// Fetch a chunk of [ManagedVideo]'s of size `num_entries` beginning
@@ -205,6 +556,11 @@ impl Database<Sqlite> {
     /// This may return less than the specified `num_entries` if the database
     /// contains less than that number.
     ///
+    /// The `starting_id` entry is included, so fetching the chunk following
+    /// another one repeats its last entry. Deprecated in favor of
+    /// [fetch_history](Self::fetch_history), which returns a [Page](history::Page)
+    /// with cursors to the pages around it instead.
+    ///
     /// # Example
     /// Consider a database with the following rows and columns:
     ///
@@ -217,10 +573,10 @@ impl Database<Sqlite> {
     /// | 5 | e |
     ///
     /// ```no_run
-    /// # use yd_gui::database::{*, sqlxResult};
+    /// # use yd_gui::{database::*, Result};
     /// # use sqlx::Sqlite;
     /// #
-    /// # async fn example(db: Database<Sqlite>) -> sqlxResult<()> {
+    /// # async fn example(db: Database<Sqlite>) -> Result<()> {
     ///
     /// // a, b, c, d, e
     /// db.fetch_chunk_of(1, 5, FetchOrd::GEQandASC).await?;
@@ -243,12 +599,13 @@ impl Database<Sqlite> {
     /// # Ok(())
     /// # }
     /// ```
+    #[deprecated(note = "use fetch_history, whose pages don't repeat their boundary rows")]
     pub async fn fetch_chunk_of(
         &self,
         starting_id: i32,
         num_entries: u32,
         ord: FetchOrd,
-    ) -> sqlxResult<Vec<ManagedVideo>> {
+    ) -> Result<Vec<ManagedVideo>> {
         let id_and_infos: Vec<IdAndInfo> = query_as(match ord {
             FetchOrd::GEQandASC => QUERY_FETCH_CHUNK_INFO_GEQ,
             FetchOrd::LEQandDESC => QUERY_FETCH_CHUNK_INFO_LEQ,
@@ -258,28 +615,16 @@ impl Database<Sqlite> {
         .fetch_all(&self.pool)
         .await?;
 
-        let mut managed_videos = Vec::new();
-        for IdAndInfo(id, mut video_info) in id_and_infos {
-            video_info.video_formats = query_as(QUERY_FETCH_ONE_FORMATS)
-                .bind(id)
-                .fetch_all(&self.pool)
-                .await?;
-            let managed_video = ManagedVideo::new(id, video_info);
-            managed_videos.push(managed_video);
-        }
-
-        Ok(managed_videos)
+        self.with_formats(id_and_infos).await
     }
 
     /// Fetch a chunk of [ManagedVideo]'s.
     ///
     /// Works exactly like [fetch_chunk_of](Database::fetch_chunk_of) but with
     /// a default `num_entries` of 20.
-    pub async fn fetch_chunk(
-        &self,
-        starting_id: i32,
-        ord: FetchOrd,
-    ) -> sqlxResult<Vec<ManagedVideo>> {
+    #[deprecated(note = "use fetch_history, whose pages don't repeat their boundary rows")]
+    #[allow(deprecated)]
+    pub async fn fetch_chunk(&self, starting_id: i32, ord: FetchOrd) -> Result<Vec<ManagedVideo>> {
         self.fetch_chunk_of(starting_id, 20, ord).await
     }
 
@@ -287,7 +632,9 @@ impl Database<Sqlite> {
     ///
     /// Equivalent to using [fetch_chunk(1, FetchOrd::GEQandASC)](Self::fetch_chunk).
     /// See also [fetch_first_chunk_from_bottom](Self::fetch_first_chunk_from_bottom).
-    pub async fn fetch_first_chunk_from_top(&self) -> sqlxResult<Vec<ManagedVideo>> {
+    #[deprecated(note = "use fetch_history, whose pages don't repeat their boundary rows")]
+    #[allow(deprecated)]
+    pub async fn fetch_first_chunk_from_top(&self) -> Result<Vec<ManagedVideo>> {
         self.fetch_chunk(1, FetchOrd::GEQandASC).await
     }
 
@@ -296,11 +643,13 @@ impl Database<Sqlite> {
     /// Equivalent, *in spirit*, to using
     /// [fetch_chunk(*\[last id in database\]*, FetchOrd::LEQandDESC)](Self::fetch_chunk).
     /// See also [fetch_first_chunk_from_top](Self::fetch_first_chunk_from_top).
-    pub async fn fetch_first_chunk_from_bottom(&self) -> sqlxResult<Vec<ManagedVideo>> {
+    #[deprecated(note = "use fetch_history, whose pages don't repeat their boundary rows")]
+    pub async fn fetch_first_chunk_from_bottom(&self) -> Result<Vec<ManagedVideo>> {
         const QUERY_FETCH_CHUNK_INFO_BOTTOM: &str = formatcp!(
             "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
-                {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}
+                {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
              FROM {VIDEO_INFO}
+             WHERE {DELETED_AT} IS NULL
              ORDER BY {ID} DESC
              LIMIT $1
             "
@@ -311,103 +660,400 @@ impl Database<Sqlite> {
             .fetch_all(&self.pool)
             .await?;
 
-        let mut managed_videos = Vec::new();
-        for IdAndInfo(id, mut video_info) in id_and_infos {
-            video_info.video_formats = query_as(QUERY_FETCH_ONE_FORMATS)
-                .bind(id)
-                .fetch_all(&self.pool)
-                .await?;
-            let managed_video = ManagedVideo::new(id, video_info);
-            managed_videos.push(managed_video);
-        }
+        self.with_formats(id_and_infos).await
+    }
+
+    /// Search the titles and authors of the videos for `query`, the most
+    /// relevant first. Every word of `query` must match the start of a word,
+    /// ignoring case and diacritics.
+    ///
+    /// At most `limit` videos are returned. To fetch the following ones,
+    /// pass the number of videos already fetched as the `cursor`.
+    pub async fn search(&self, query: &str, limit: u32, cursor: u32) -> Result<Vec<ManagedVideo>> {
+        const QUERY: &str = formatcp!(
+            "SELECT {VIDEO_INFO}.{ID}, {VIDEO_ID}, {VIDEO_INFO}.{TITLE}, {VIDEO_INFO}.{AUTHOR},
+                {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
+             FROM {VIDEO_SEARCH}
+             JOIN {VIDEO_INFO} ON {VIDEO_INFO}.{ID} = {VIDEO_SEARCH}.rowid
+             WHERE {VIDEO_SEARCH} MATCH $1 AND {DELETED_AT} IS NULL
+             ORDER BY rank, {VIDEO_INFO}.{ID} DESC
+             LIMIT $2 OFFSET $3
+            "
+        );
+        let Some(fts_query) = to_fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let id_and_infos: Vec<IdAndInfo> = query_as(QUERY)
+            .bind(fts_query)
+            .bind(limit)
+            .bind(cursor)
+            .fetch_all(&self.pool)
+            .await?;
+
+        self.with_formats(id_and_infos).await
+    }
+
+    /// Fetch the [ManagedVideo] of the YouTube video with the matching
+    /// `video_id`, `None` if it's not in the history or in the trash.
+    pub async fn find_by_video_id(&self, video_id: &str) -> Result<Option<ManagedVideo>> {
+        const QUERY: &str = formatcp!(
+            "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
+                {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
+             FROM {VIDEO_INFO}
+             WHERE {VIDEO_ID} = $1 AND {DELETED_AT} IS NULL
+            "
+        );
+        let Some(IdAndInfo(id, mut video_info, record)) = query_as(QUERY)
+            .bind(video_id)
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(None);
+        };
 
-        Ok(managed_videos)
+        video_info.video_formats = query_as(QUERY_FETCH_ONE_FORMATS)
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Some(ManagedVideo::new(id, video_info).with_record(record)))
     }
 
     /// Insert `video_info` into the database.
     /// Returns the row id of the inserted video.
     ///
+    /// # Errors
+    /// Fails if a video with the same [video_id](VideoInfo::video_id) is
+    /// already in the database, see [upsert_video_info](Self::upsert_video_info).
+    ///
     /// See also [insert_bulk_video_info](Self::insert_bulk_video_info).
-    pub async fn insert_video_info(&self, video_info: &VideoInfo) -> sqlxResult<i32> {
-        let mut transaction = self.get_transaction().await?;
-
-        // Insertion into video_info table
-        let id: i32 = query_scalar(QUERY_INSERT_INFO)
-            .bind(&video_info.video_id)
-            .bind(&video_info.title)
-            .bind(&video_info.author)
-            .bind(&video_info.duration_seconds)
-            .bind(&video_info.thumbnail)
-            .bind(video_info.audio_available)
-            .fetch_one(&mut *transaction)
-            .await?;
+    pub async fn insert_video_info(&self, video_info: &VideoInfo) -> Result<i32> {
+        retry_busy(|| async move {
+            let mut transaction = self.get_transaction().await?;
 
-        // Insertion(s) into video_format table
-        for video_format in &video_info.video_formats {
-            query(QUERY_INSERT_FORMAT)
-                .bind(&video_format.container)
-                .bind(&video_format.width)
-                .bind(&video_format.height)
-                .bind(&video_format.fps)
-                .bind(id)
-                .execute(&mut *transaction)
+            // Insertion into video_info table
+            let id: i32 = query_scalar(QUERY_INSERT_INFO)
+                .bind(&video_info.video_id)
+                .bind(&video_info.title)
+                .bind(&video_info.author)
+                .bind(video_info.duration.as_secs() as i64)
+                .bind(&video_info.thumbnail)
+                .bind(video_info.audio_available)
+                .fetch_one(&mut *transaction)
                 .await?;
-        }
 
-        transaction.commit().await?;
+            // Insertion(s) into video_format table
+            insert_formats(&mut transaction, id, &video_info.video_formats).await?;
 
-        Ok(id)
+            transaction.commit().await?;
+
+            Ok(id)
+        })
+        .await
     }
 
-    /// Insert `video_infos` into the database.
-    /// Returns the respective row ids of the `video_infos`.
-    ///
-    /// See also [insert_video_info](Self::insert_video_info).
-    pub async fn insert_bulk_video_info(
-        &self,
-        video_infos: &Vec<VideoInfo>,
-    ) -> sqlxResult<Vec<i32>> {
-        let mut transaction = self.get_transaction().await?;
+    /// Insert `video_info` into the database, or refresh the metadata and
+    /// formats of the video with the same [video_id](VideoInfo::video_id).
+    /// Returns the row id of the video, which is kept along with its
+    /// [DownloadRecord] if it was already there, and taken out of the trash.
+    pub async fn upsert_video_info(&self, video_info: &VideoInfo) -> Result<i32> {
+        const QUERY: &str = formatcp!(
+            "INSERT INTO {VIDEO_INFO}
+                ({VIDEO_ID}, {TITLE}, {AUTHOR},
+                    {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {ADDED_AT})
+             VALUES
+                ($1, $2, $3,
+                    $4, $5, $6, CAST(strftime('%s', 'now') AS INTEGER))
+             ON CONFLICT ({VIDEO_ID}) DO UPDATE SET
+                {TITLE} = excluded.{TITLE},
+                {AUTHOR} = excluded.{AUTHOR},
+                {DURATION_SECONDS} = excluded.{DURATION_SECONDS},
+                {THUMBNAIL} = excluded.{THUMBNAIL},
+                {AUDIO_AVAILABLE} = excluded.{AUDIO_AVAILABLE},
+                {DELETED_AT} = NULL
+             RETURNING
+                {ID}
+            "
+        );
+        const QUERY_DELETE_FORMATS: &str =
+            formatcp!("DELETE FROM {VIDEO_FORMAT} WHERE {VIDEO_INFO_ID} = $1");
+        retry_busy(|| async move {
+            let mut transaction = self.get_transaction().await?;
 
-        let mut res = Vec::with_capacity(video_infos.len());
-        for video_info in video_infos {
-            let id: i32 = query_scalar(QUERY_INSERT_INFO)
+            let id: i32 = query_scalar(QUERY)
                 .bind(&video_info.video_id)
                 .bind(&video_info.title)
                 .bind(&video_info.author)
-                .bind(&video_info.duration_seconds)
+                .bind(video_info.duration.as_secs() as i64)
                 .bind(&video_info.thumbnail)
                 .bind(video_info.audio_available)
                 .fetch_one(&mut *transaction)
                 .await?;
-            for video_format in &video_info.video_formats {
-                query(QUERY_INSERT_FORMAT)
-                    .bind(&video_format.container)
-                    .bind(&video_format.width)
-                    .bind(&video_format.height)
-                    .bind(&video_format.fps)
-                    .bind(id)
-                    .execute(&mut *transaction)
+
+            query(QUERY_DELETE_FORMATS)
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+            insert_formats(&mut transaction, id, &video_info.video_formats).await?;
+
+            transaction.commit().await?;
+
+            Ok(id)
+        })
+        .await
+    }
+
+    /// Insert `video_infos` into the database.
+    /// Returns the respective row ids of the `video_infos`.
+    ///
+    /// # Errors
+    /// Fails without inserting any video if one of them is already in the
+    /// database or appears twice in `video_infos`.
+    ///
+    /// See also [insert_video_info](Self::insert_video_info).
+    pub async fn insert_bulk_video_info(&self, video_infos: &[VideoInfo]) -> Result<Vec<i32>> {
+        let inserted = self
+            .insert_bulk_video_info_with(video_infos, OnExisting::Fail)
+            .await?;
+
+        Ok(inserted
+            .into_iter()
+            .filter_map(|inserted| match inserted {
+                Inserted::New(id) => Some(id),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Insert `video_infos` into the database in a single transaction,
+    /// treating those already there as `on_existing` says.
+    /// Returns what was done with each of the `video_infos`, in order.
+    ///
+    /// # Errors
+    /// Fails without inserting any video if the insertion fails, e.g. if a
+    /// video is already in the database with [OnExisting::Fail].
+    pub async fn insert_bulk_video_info_with(
+        &self,
+        video_infos: &[VideoInfo],
+        on_existing: OnExisting,
+    ) -> Result<Vec<Inserted>> {
+        const QUERY_RESTORE: &str = formatcp!(
+            "UPDATE {VIDEO_INFO} SET {DELETED_AT} = NULL
+             WHERE {VIDEO_ID} = $1 AND {DELETED_AT} IS NOT NULL
+             RETURNING {ID}"
+        );
+        let query_insert = match on_existing {
+            OnExisting::Fail => QUERY_INSERT_INFO,
+            OnExisting::SkipOrRestore => QUERY_INSERT_NEW_INFO,
+        };
+        retry_busy(|| async move {
+            let mut transaction = self.get_transaction().await?;
+
+            let mut res = Vec::with_capacity(video_infos.len());
+            for video_info in video_infos {
+                if on_existing == OnExisting::SkipOrRestore {
+                    let restored: Option<i32> = query_scalar(QUERY_RESTORE)
+                        .bind(&video_info.video_id)
+                        .fetch_optional(&mut *transaction)
+                        .await?;
+                    if let Some(id) = restored {
+                        res.push(Inserted::Restored(id));
+                        continue;
+                    }
+                }
+
+                let id: Option<i32> = query_scalar(query_insert)
+                    .bind(&video_info.video_id)
+                    .bind(&video_info.title)
+                    .bind(&video_info.author)
+                    .bind(video_info.duration.as_secs() as i64)
+                    .bind(&video_info.thumbnail)
+                    .bind(video_info.audio_available)
+                    .fetch_optional(&mut *transaction)
                     .await?;
+                match id {
+                    Some(id) => {
+                        insert_formats(&mut transaction, id, &video_info.video_formats).await?;
+                        res.push(Inserted::New(id));
+                    }
+                    None => res.push(Inserted::Skipped),
+                }
             }
-            res.push(id);
-        }
 
-        transaction.commit().await?;
+            transaction.commit().await?;
+
+            Ok(res)
+        })
+        .await
+    }
+
+    /// Record that the video with the matching row `id` was downloaded to
+    /// `path`, a file of `size` bytes.
+    pub async fn mark_downloaded(&self, id: i32, path: impl AsRef<Path>, size: u64) -> Result<u64> {
+        const QUERY: &str = formatcp!(
+            "UPDATE {VIDEO_INFO}
+             SET {STATUS} = $1, {DOWNLOADED_AT} = $2, {OUTPUT_PATH} = $3, {FILE_SIZE} = $4
+             WHERE {ID} = $5
+            "
+        );
+        let result = query(QUERY)
+            .bind(DownloadStatus::Downloaded)
+            .bind(unix_seconds(SystemTime::now()))
+            .bind(path.as_ref().to_string_lossy())
+            .bind(size as i64)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
 
-        Ok(res)
+        Ok(result.rows_affected())
     }
 
-    /// Delete the video at the row with the matching row `id`.
-    pub async fn delete_video_info(&self, id: i32) -> sqlxResult<u64> {
-        const QUERY: &str = formatcp!("DELETE FROM {VIDEO_INFO} WHERE {ID} = $1");
+    /// Record that the last attempt to download the video with the matching
+    /// row `id` failed.
+    pub async fn mark_failed(&self, id: i32) -> Result<u64> {
+        const QUERY: &str = formatcp!("UPDATE {VIDEO_INFO} SET {STATUS} = $1 WHERE {ID} = $2");
+        let result = query(QUERY)
+            .bind(DownloadStatus::Failed)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Record the [format_id](VideoFormat::format_id) of the format chosen
+    /// to download the video with the matching row `id`.
+    pub async fn set_chosen_format_id(&self, id: i32, format_id: &str) -> Result<u64> {
+        const QUERY: &str =
+            formatcp!("UPDATE {VIDEO_INFO} SET {CHOSEN_FORMAT_ID} = $1 WHERE {ID} = $2");
+        let result = query(QUERY)
+            .bind(format_id)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Move the video with the matching row `id` to the trash, out of the
+    /// chunks, history and search results until it's [restored](Self::restore).
+    pub async fn trash(&self, id: i32) -> Result<u64> {
+        const QUERY: &str = formatcp!(
+            "UPDATE {VIDEO_INFO} SET {DELETED_AT} = $1
+             WHERE {ID} = $2 AND {DELETED_AT} IS NULL
+            "
+        );
+        let result = query(QUERY)
+            .bind(unix_seconds(SystemTime::now()))
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Move all the videos to the trash.
+    pub async fn trash_all(&self) -> Result<u64> {
+        const QUERY: &str =
+            formatcp!("UPDATE {VIDEO_INFO} SET {DELETED_AT} = $1 WHERE {DELETED_AT} IS NULL");
+        let result = query(QUERY)
+            .bind(unix_seconds(SystemTime::now()))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Take the video with the matching row `id` out of the trash.
+    pub async fn restore(&self, id: i32) -> Result<u64> {
+        const QUERY: &str = formatcp!(
+            "UPDATE {VIDEO_INFO} SET {DELETED_AT} = NULL
+             WHERE {ID} = $1 AND {DELETED_AT} IS NOT NULL
+            "
+        );
         let result = query(QUERY).bind(id).execute(&self.pool).await?;
 
         Ok(result.rows_affected())
     }
 
+    /// Fetch the videos in the trash, the most recently trashed first.
+    pub async fn fetch_trash(&self) -> Result<Vec<ManagedVideo>> {
+        const QUERY: &str = formatcp!(
+            "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
+                {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
+             FROM {VIDEO_INFO}
+             WHERE {DELETED_AT} IS NOT NULL
+             ORDER BY {DELETED_AT} DESC, {ID} DESC
+            "
+        );
+        let id_and_infos: Vec<IdAndInfo> = query_as(QUERY).fetch_all(&self.pool).await?;
+
+        self.with_formats(id_and_infos).await
+    }
+
+    /// Delete for good the videos that were moved to the trash at least
+    /// `older_than` ago. [Duration::ZERO] empties the trash.
+    pub async fn purge_trash(&self, older_than: Duration) -> Result<u64> {
+        const QUERY: &str = formatcp!("DELETE FROM {VIDEO_INFO} WHERE {DELETED_AT} <= $1");
+        let before = SystemTime::now()
+            .checked_sub(older_than)
+            .unwrap_or(UNIX_EPOCH);
+        let result = query(QUERY)
+            .bind(unix_seconds(before))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete the video at the row with the matching row `id`, along with its
+    /// formats, and its downloaded file depending on the `mode`.
+    /// See [trash](Self::trash) to be able to restore it.
+    ///
+    /// The row is deleted first, failing to remove the file afterwards is
+    /// reported in the [DeleteReport] rather than as an error. A file that
+    /// is already gone isn't a failure.
+    pub async fn delete_video_info(&self, id: i32, mode: DeleteMode) -> Result<DeleteReport> {
+        const QUERY: &str = formatcp!(
+            "DELETE FROM {VIDEO_INFO} WHERE {ID} = $1
+             RETURNING {OUTPUT_PATH}
+            "
+        );
+        // Fetching all the rows runs the statement to completion, which
+        // the cascade to the other tables waits for
+        let output_paths: Vec<Option<String>> =
+            query_scalar(QUERY).bind(id).fetch_all(&self.pool).await?;
+
+        let mut report = DeleteReport {
+            rows_deleted: output_paths.len() as u64,
+            output_path: output_paths.into_iter().flatten().next().map(PathBuf::from),
+            trashed_to: None,
+            file_error: None,
+        };
+        let Some(path) = report.output_path.clone() else {
+            return Ok(report);
+        };
+        let removed = match mode {
+            DeleteMode::RowOnly => Ok(()),
+            DeleteMode::RowAndFile => tokio::fs::remove_file(path).await,
+            DeleteMode::FileToSystemTrash => {
+                tokio::task::spawn_blocking(move || system_trash::move_to_trash(path))
+                    .await
+                    .map_err(std::io::Error::other)
+                    .and_then(|trashed| trashed)
+                    .map(|trashed_to| report.trashed_to = Some(trashed_to))
+            }
+        };
+        match removed {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => report.file_error = Some(e),
+            _ => {}
+        }
+
+        Ok(report)
+    }
+
     /// <div class="warning">Deletes all the videos in the database.</div>
-    pub async fn delete_all(&self) -> sqlxResult<u64> {
+    pub async fn delete_all(&self) -> Result<u64> {
         const QUERY: &str = formatcp!("DELETE FROM {VIDEO_INFO}");
         let result = query(QUERY).execute(&self.pool).await?;
 
@@ -415,16 +1061,159 @@ impl Database<Sqlite> {
     }
 }
 
+const DOWNLOAD_QUEUE: &str = "download_queue";
+const URL: &str = "url";
+const DESTINATION: &str = "destination";
+const PAUSED: &str = "paused";
+const RESUME_OFFSET: &str = "resume_offset";
+
+/// A download that hasn't finished yet, kept in the database so the
+/// [DownloadQueue](crate::download::queue::DownloadQueue) survives a restart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingDownload {
+    /// Row id of the video being downloaded
+    pub video_info_id: i32,
+    pub format: VideoFormat,
+    pub destination: PathBuf,
+    pub paused: bool,
+    /// How many bytes were downloaded when the download was last interrupted
+    pub resume_offset: u64,
+}
+
+struct IdAndPending(i32, PendingDownload);
+impl FromRow<'_, SqliteRow> for IdAndPending {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self(
+            row.try_get(ID)?,
+            PendingDownload {
+                video_info_id: row.try_get(VIDEO_INFO_ID)?,
+                format: VideoFormat {
+                    url: row.try_get(URL)?,
+                    ..VideoFormat::from_row(row)?
+                },
+                destination: PathBuf::from(row.try_get::<String, _>(DESTINATION)?),
+                paused: row.try_get(PAUSED)?,
+                resume_offset: row.try_get::<i64, _>(RESUME_OFFSET)? as u64,
+            },
+        ))
+    }
+}
+
+impl Database<Sqlite> {
+    /// Insert `pending` into the download queue.
+    /// Returns the row id of the queued download.
+    pub async fn insert_pending_download(&self, pending: &PendingDownload) -> Result<i32> {
+        const QUERY: &str = formatcp!(
+            "INSERT INTO {DOWNLOAD_QUEUE}
+                ({FORMAT_ID}, {CONTAINER}, {WIDTH}, {HEIGHT}, {FPS}, {VCODEC}, {ACODEC},
+                    {BITRATE_KBPS}, {FILESIZE}, {HDR}, {IS_AUDIO_ONLY}, {URL},
+                    {DESTINATION}, {PAUSED}, {RESUME_OFFSET}, {VIDEO_INFO_ID})
+             VALUES
+                ($1, $2, $3, $4, $5, $6, $7,
+                    $8, $9, $10, $11, $12,
+                    $13, $14, $15, $16)
+             RETURNING
+                {ID}
+            "
+        );
+        let id = query_scalar(QUERY)
+            .bind(&pending.format.format_id)
+            .bind(&pending.format.container)
+            .bind(pending.format.width)
+            .bind(pending.format.height)
+            .bind(pending.format.fps)
+            .bind(&pending.format.vcodec)
+            .bind(&pending.format.acodec)
+            .bind(pending.format.bitrate_kbps)
+            .bind(pending.format.filesize.map(|filesize| filesize as i64))
+            .bind(pending.format.hdr)
+            .bind(pending.format.is_audio_only)
+            .bind(&pending.format.url)
+            .bind(pending.destination.to_string_lossy())
+            .bind(pending.paused)
+            .bind(pending.resume_offset as i64)
+            .bind(pending.video_info_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Fetch every queued download along with its row id, in the order they
+    /// were queued.
+    pub async fn fetch_pending_downloads(&self) -> Result<Vec<(i32, PendingDownload)>> {
+        const QUERY: &str = formatcp!(
+            "SELECT {ID}, {FORMAT_ID}, {CONTAINER}, {WIDTH}, {HEIGHT}, {FPS}, {VCODEC}, {ACODEC},
+                {BITRATE_KBPS}, {FILESIZE}, {HDR}, {IS_AUDIO_ONLY}, {URL},
+                {DESTINATION}, {PAUSED}, {RESUME_OFFSET}, {VIDEO_INFO_ID}
+             FROM {DOWNLOAD_QUEUE}
+             ORDER BY {ID} ASC
+            "
+        );
+        let id_and_pendings: Vec<IdAndPending> = query_as(QUERY).fetch_all(&self.pool).await?;
+
+        Ok(id_and_pendings
+            .into_iter()
+            .map(|IdAndPending(id, pending)| (id, pending))
+            .collect())
+    }
+
+    /// Set whether the queued download with the matching row `id` is paused.
+    pub async fn set_pending_download_paused(&self, id: i32, paused: bool) -> Result<u64> {
+        const QUERY: &str = formatcp!("UPDATE {DOWNLOAD_QUEUE} SET {PAUSED} = $1 WHERE {ID} = $2");
+        let result = query(QUERY)
+            .bind(paused)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Record how many bytes of the queued download with the matching row `id`
+    /// were downloaded when it got interrupted.
+    pub async fn set_pending_download_offset(&self, id: i32, resume_offset: u64) -> Result<u64> {
+        const QUERY: &str =
+            formatcp!("UPDATE {DOWNLOAD_QUEUE} SET {RESUME_OFFSET} = $1 WHERE {ID} = $2");
+        let result = query(QUERY)
+            .bind(resume_offset as i64)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Remove the queued download with the matching row `id`.
+    pub async fn delete_pending_download(&self, id: i32) -> Result<u64> {
+        const QUERY: &str = formatcp!("DELETE FROM {DOWNLOAD_QUEUE} WHERE {ID} = $1");
+        let result = query(QUERY).bind(id).execute(&self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{
+        cell::Cell,
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
     use crate::{
-        database::FetchOrd,
-        video::{ManagedVideo, VideoFormat, VideoInfo},
+        database::{
+            history::HistoryQuery, DeleteMode, FetchOrd, IdAndInfo, PendingDownload, PoolOptions,
+            FORMAT_QUERIES, QUERY_FETCH_CHUNK_INFO_GEQ, QUERY_FETCH_ONE_FORMATS,
+        },
+        test_util::{test_videos, TempDir},
+        video::{DownloadStatus, ManagedVideo, VideoFormat, VideoInfo},
+        Error,
     };
 
     use super::Database;
     use anyhow::{Ok, Result};
-    use sqlx::{migrate, SqlitePool};
+    use sqlx::{migrate, query, query_as, query_scalar, Executor, Sqlite, SqlitePool};
 
     #[sqlx::test]
     async fn init_database() -> Result<()> {
@@ -447,82 +1236,120 @@ mod tests {
         Ok(migrate!().run(&pool).await?)
     }
 
-    fn get_test_videos() -> Vec<VideoInfo> {
-        vec![
-            VideoInfo {
-                video_id: "id1".to_string(),
-                title: "Video 1".to_string(),
-                author: "Author 1".to_string(),
-                duration_seconds: "1".to_string(),
-                thumbnail: None,
-                video_formats: vec![
-                    VideoFormat {
-                        container: "webm".to_string(),
-                        width: "640".to_string(),
-                        height: "480".to_string(),
-                        fps: "30".to_string(),
-                    },
-                    VideoFormat {
-                        container: "mp4".to_string(),
-                        width: "1280".to_string(),
-                        height: "720".to_string(),
-                        fps: "60".to_string(),
-                    },
-                ],
-                audio_available: true,
-            },
-            VideoInfo {
-                video_id: "id2".to_string(),
-                title: "Video 2".to_string(),
-                author: "Author 2".to_string(),
-                duration_seconds: "2".to_string(),
-                thumbnail: None,
-                video_formats: vec![
-                    VideoFormat {
-                        container: "webm".to_string(),
-                        width: "640".to_string(),
-                        height: "480".to_string(),
-                        fps: "30".to_string(),
-                    },
-                    VideoFormat {
-                        container: "mp4".to_string(),
-                        width: "1280".to_string(),
-                        height: "720".to_string(),
-                        fps: "60".to_string(),
-                    },
-                ],
-                audio_available: false,
-            },
-            VideoInfo {
-                video_id: "id3".to_string(),
-                title: "Video 3".to_string(),
-                author: "Author 3".to_string(),
-                duration_seconds: "3".to_string(),
-                thumbnail: None,
-                video_formats: vec![
-                    VideoFormat {
-                        container: "webm".to_string(),
-                        width: "640".to_string(),
-                        height: "480".to_string(),
-                        fps: "30".to_string(),
-                    },
-                    VideoFormat {
-                        container: "mp4".to_string(),
-                        width: "1280".to_string(),
-                        height: "720".to_string(),
-                        fps: "60".to_string(),
-                    },
-                ],
-                audio_available: true,
+    /// History files written before the columns became numeric stay readable.
+    #[sqlx::test(migrations = false)]
+    async fn migrate_text_columns(pool: SqlitePool) -> Result<()> {
+        let migrator = migrate!();
+        let (old, new): (Vec<_>, Vec<_>) = migrator.iter().partition(|m| m.version < 4);
+        for migration in old {
+            pool.execute(&*migration.sql).await?;
+        }
+
+        pool.execute(
+            "INSERT INTO video_info
+                (video_id, title, author, duration_seconds, thumbnail, audio_available)
+             VALUES ('id1', 'Video 1', 'Author 1', '212', NULL, 1);
+             INSERT INTO video_format (container, width, height, fps, video_info_id)
+             VALUES ('webm', '1920', '1080', '59.94', 1);
+             INSERT INTO download_queue
+                (container, width, height, fps, url, destination, paused, video_info_id)
+             VALUES ('webm', '1920', '1080', '59.94', NULL, '/tmp/video.webm', 0, 1);",
+        )
+        .await?;
+
+        for migration in new {
+            pool.execute(&*migration.sql).await?;
+        }
+
+        let db = Database { pool };
+        let video = db.fetch_one(1).await?;
+        let format = VideoFormat {
+            container: "webm".to_string(),
+            width: 1920,
+            height: 1080,
+            fps: 59.94,
+            ..Default::default()
+        };
+        assert_eq!(video.get_info().duration, Duration::from_secs(212));
+        assert_eq!(video.get_info().video_formats, vec![format.clone()]);
+
+        let pending = db.fetch_pending_downloads().await?;
+        assert_eq!(pending[0].1.format, format);
+
+        // Videos added before the search index are indexed
+        assert_eq!(db.search("video", 10, 0).await?.len(), 1);
+
+        // Deleting the video still cascades to the recreated tables
+        db.delete_video_info(1, DeleteMode::RowOnly).await?;
+        assert!(db.fetch_pending_downloads().await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writes_and_reads() -> Result<()> {
+        const WRITERS: usize = 8;
+        const VIDEOS_PER_WRITER: usize = 25;
+        const READERS: usize = 4;
+
+        let dir = TempDir::new("concurrent");
+        let db = Database::init_with_options(
+            dir.join("history.db"),
+            PoolOptions {
+                max_connections: 4,
+                ..Default::default()
             },
-        ]
+        )
+        .await?;
+
+        let journal_mode: String = query_scalar("PRAGMA journal_mode")
+            .fetch_one(&db.pool)
+            .await?;
+        assert_eq!(journal_mode, "wal");
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for writer in 0..WRITERS {
+            let db = db.clone();
+            tasks.spawn(async move {
+                for i in 0..VIDEOS_PER_WRITER {
+                    let mut video_info = test_videos()[0].clone();
+                    video_info.video_id = format!("w{writer}-{i}");
+                    let id = db.insert_video_info(&video_info).await?;
+                    db.mark_downloaded(id, "/tmp/video.mp4", 1).await?;
+                }
+                Ok(())
+            });
+        }
+        for _ in 0..READERS {
+            let db = db.clone();
+            tasks.spawn(async move {
+                for _ in 0..VIDEOS_PER_WRITER {
+                    db.fetch_history(&HistoryQuery::new()).await?;
+                }
+                Ok(())
+            });
+        }
+        while let Some(result) = tasks.join_next().await {
+            result??;
+        }
+
+        let count: i64 = query_scalar("SELECT COUNT(*) FROM video_info")
+            .fetch_one(&db.pool)
+            .await?;
+        assert_eq!(count as usize, WRITERS * VIDEOS_PER_WRITER);
+
+        // Everything is in the database file once closed
+        db.close().await;
+        let wal_len = std::fs::metadata(dir.join("history.db-wal")).map_or(0, |wal| wal.len());
+        assert_eq!(wal_len, 0);
+        Ok(())
     }
 
     #[sqlx::test]
     async fn fetch_one(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_video = &get_test_videos()[0];
+        let test_video = &test_videos()[0];
 
         let id = db.insert_video_info(test_video).await.unwrap();
 
@@ -531,11 +1358,12 @@ mod tests {
         assert_eq!(db_video.get_info(), test_video);
     }
 
+    #[allow(deprecated)]
     #[sqlx::test]
     async fn fetch_chunk_of(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_videos = get_test_videos();
+        let test_videos = test_videos();
 
         db.insert_bulk_video_info(&test_videos).await.unwrap();
 
@@ -550,11 +1378,12 @@ mod tests {
         assert_eq!(db_videos, test_videos);
     }
 
+    #[allow(deprecated)]
     #[sqlx::test]
     async fn fetch_chunk(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_videos = get_test_videos();
+        let test_videos = test_videos();
 
         db.insert_bulk_video_info(&test_videos).await.unwrap();
 
@@ -569,11 +1398,12 @@ mod tests {
         assert_eq!(db_videos, test_videos);
     }
 
+    #[allow(deprecated)]
     #[sqlx::test]
     async fn fetch_first_chunk_from_top(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_videos = get_test_videos();
+        let test_videos = test_videos();
 
         db.insert_bulk_video_info(&test_videos).await.unwrap();
 
@@ -588,11 +1418,12 @@ mod tests {
         assert_eq!(db_videos, test_videos);
     }
 
+    #[allow(deprecated)]
     #[sqlx::test]
     async fn fetch_first_chunk_from_bottom(pool: SqlitePool) {
         let db = Database { pool };
 
-        let mut test_videos = get_test_videos();
+        let mut test_videos = test_videos();
 
         db.insert_bulk_video_info(&test_videos).await.unwrap();
 
@@ -609,11 +1440,213 @@ mod tests {
         assert_eq!(db_videos, test_videos);
     }
 
+    /// `count` test videos, some of them without formats.
+    fn many_test_videos(count: usize) -> Vec<VideoInfo> {
+        let template = &test_videos()[0];
+        (0..count)
+            .map(|i| VideoInfo {
+                video_id: format!("id{i}"),
+                title: format!("Video {i}"),
+                // Some videos have no format
+                video_formats: template.video_formats[..i % 4].to_vec(),
+                ..template.clone()
+            })
+            .collect()
+    }
+
+    /// Every page of the history, with one query for the formats of each video.
+    async fn fetch_all_one_by_one(db: &Database<Sqlite>) -> Result<Vec<VideoInfo>> {
+        let mut videos = Vec::new();
+        let mut starting_id = 1;
+        loop {
+            let id_and_infos: Vec<IdAndInfo> = query_as(QUERY_FETCH_CHUNK_INFO_GEQ)
+                .bind(starting_id)
+                .bind(20)
+                .fetch_all(&db.pool)
+                .await?;
+            let Some(IdAndInfo(last_id, ..)) = id_and_infos.last() else {
+                return Ok(videos);
+            };
+            starting_id = last_id + 1;
+            for IdAndInfo(id, mut video_info, _) in id_and_infos {
+                video_info.video_formats = query_as(QUERY_FETCH_ONE_FORMATS)
+                    .bind(id)
+                    .fetch_all(&db.pool)
+                    .await?;
+                videos.push(video_info);
+            }
+        }
+    }
+
+    /// Every page of the history, with one query for the formats of each page.
+    #[allow(deprecated)]
+    async fn fetch_all_batched(db: &Database<Sqlite>) -> Result<Vec<VideoInfo>> {
+        let mut videos = Vec::new();
+        let mut starting_id = 1;
+        loop {
+            let chunk = db.fetch_chunk(starting_id, FetchOrd::GEQandASC).await?;
+            let Some(last) = chunk.last() else {
+                return Ok(videos);
+            };
+            starting_id = last.get_id() + 1;
+            videos.extend(chunk.into_iter().map(VideoInfo::from));
+        }
+    }
+
+    /// Fetching the formats of a chunk at once gives each video its own formats.
+    #[sqlx::test]
+    async fn batched_formats(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+
+        let test_videos = many_test_videos(100);
+        db.insert_bulk_video_info(&test_videos).await?;
+
+        assert_eq!(fetch_all_batched(&db).await?, test_videos);
+        assert_eq!(fetch_all_one_by_one(&db).await?, test_videos);
+
+        Ok(())
+    }
+
+    /// How many videos `fetch` returned, and how many times it queried formats.
+    async fn count_format_queries(
+        fetch: impl std::future::Future<Output = crate::Result<Vec<ManagedVideo>>>,
+    ) -> Result<(usize, usize)> {
+        FORMAT_QUERIES
+            .scope(Cell::new(0), async {
+                let videos = fetch.await?;
+                Ok((videos.len(), FORMAT_QUERIES.with(Cell::get)))
+            })
+            .await
+    }
+
+    /// However large the pages, their formats are fetched in a single query.
+    #[sqlx::test]
+    #[allow(deprecated)]
+    async fn batched_formats_query_count(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+
+        let test_videos = many_test_videos(100);
+        let ids = db.insert_bulk_video_info(&test_videos).await?;
+        let page_size = test_videos.len() as u32;
+
+        let chunk = db.fetch_chunk_of(ids[0], page_size, FetchOrd::GEQandASC);
+        assert_eq!(count_format_queries(chunk).await?, (test_videos.len(), 1));
+        let chunk = db.fetch_chunk(ids[0], FetchOrd::GEQandASC);
+        assert_eq!(count_format_queries(chunk).await?, (20, 1));
+        let chunk = db.fetch_first_chunk_from_bottom();
+        assert_eq!(count_format_queries(chunk).await?, (20, 1));
+        let query = HistoryQuery::new().limit(page_size);
+        let page = async { db.fetch_history(&query).await.map(|page| page.items) };
+        assert_eq!(count_format_queries(page).await?, (test_videos.len(), 1));
+
+        // Nothing to query for an empty page
+        let chunk = db.fetch_chunk(0, FetchOrd::LEQandDESC);
+        assert_eq!(count_format_queries(chunk).await?, (0, 0));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn search(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+
+        let mut test_videos = test_videos();
+        test_videos[0].title = "Café del Mar chill mix".to_string();
+        test_videos[1].title = "Rust async explained".to_string();
+        test_videos[1].author = "Café Codeur".to_string();
+        test_videos[2].title = "Cafe racer build, cafe racer ride".to_string();
+        let ids = db.insert_bulk_video_info(&test_videos).await?;
+
+        let titles = |videos: Vec<ManagedVideo>| -> Vec<String> {
+            videos
+                .into_iter()
+                .map(|video| video.get_info().title.clone())
+                .collect()
+        };
+
+        // Diacritics and case are ignored, the most relevant comes first
+        let found = db.search("CAFE", 10, 0).await?;
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].get_info(), &test_videos[2]);
+
+        // Every word must match the start of a word
+        let found = titles(db.search("caf mi", 10, 0).await?);
+        assert_eq!(found, vec!["Café del Mar chill mix"]);
+        assert!(db.search("afe", 10, 0).await?.is_empty());
+
+        // Pages follow each other
+        let first = db.search("cafe", 2, 0).await?;
+        let second = db.search("cafe", 2, 2).await?;
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 1);
+        assert!(!first
+            .iter()
+            .any(|video| video.get_id() == second[0].get_id()));
+
+        // FTS5 syntax is searched for literally
+        assert!(db.search("\"rust\" OR NEAR(", 10, 0).await?.is_empty());
+        assert!(db.search("  ", 10, 0).await?.is_empty());
+
+        // The index follows updates and deletions
+        query("UPDATE video_info SET title = 'Rust traits' WHERE id = $1")
+            .bind(ids[1])
+            .execute(&db.pool)
+            .await?;
+        assert_eq!(
+            titles(db.search("traits", 10, 0).await?),
+            vec!["Rust traits"]
+        );
+        assert!(db.search("async", 10, 0).await?.is_empty());
+
+        db.delete_video_info(ids[2], DeleteMode::RowOnly).await?;
+        assert_eq!(db.search("racer", 10, 0).await?.len(), 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn download_record(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let id = db.insert_video_info(&test_videos()[0]).await?;
+        let record = db.fetch_one(id).await?.get_record().clone();
+        assert_eq!(record.status, DownloadStatus::Added);
+        assert!(record.added_at >= before && record.added_at <= SystemTime::now());
+        assert_eq!(record.downloaded_at, None);
+        assert_eq!(record.output_path, None);
+
+        db.set_chosen_format_id(id, "22").await?;
+        db.mark_failed(id).await?;
+        let record = db.fetch_one(id).await?.get_record().clone();
+        assert_eq!(record.status, DownloadStatus::Failed);
+        assert_eq!(record.chosen_format_id.as_deref(), Some("22"));
+
+        assert_eq!(
+            db.mark_downloaded(id, "/tmp/Video 1 [id1].mp4", 5_000_000_000)
+                .await?,
+            1
+        );
+        let video = db.fetch_one(id).await?;
+        let record = video.get_record();
+        assert_eq!(record.status, DownloadStatus::Downloaded);
+        assert_eq!(
+            record.output_path,
+            Some(PathBuf::from("/tmp/Video 1 [id1].mp4"))
+        );
+        assert_eq!(record.file_size, Some(5_000_000_000));
+        assert!(record.downloaded_at >= Some(record.added_at));
+
+        assert_eq!(db.mark_downloaded(id + 1, "/tmp/none.mp4", 0).await?, 0);
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn insert_one(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_video = &get_test_videos()[0];
+        let test_video = &test_videos()[0];
         let id = db.insert_video_info(test_video).await.unwrap();
 
         // Check primary key id
@@ -628,7 +1661,7 @@ mod tests {
     async fn insert_two_delete_one(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_videos = get_test_videos();
+        let test_videos = test_videos();
 
         let first_id = db.insert_video_info(&test_videos[0]).await.unwrap();
         let second_id = db.insert_video_info(&test_videos[1]).await.unwrap();
@@ -645,15 +1678,18 @@ mod tests {
         assert_eq!(&test_videos[1], second_video.get_info());
 
         // Check successful deletion
-        let rows_deleted = db.delete_video_info(first_id).await.unwrap();
-        assert_eq!(rows_deleted, 1);
+        let report = db
+            .delete_video_info(first_id, DeleteMode::RowOnly)
+            .await
+            .unwrap();
+        assert_eq!(report.rows_deleted, 1);
     }
 
     #[sqlx::test]
     async fn bulk_insert(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_videos = get_test_videos();
+        let test_videos = test_videos();
         let ids = db.insert_bulk_video_info(&test_videos).await.unwrap();
 
         // Check primary key ids
@@ -675,11 +1711,55 @@ mod tests {
         }
     }
 
+    #[sqlx::test]
+    async fn delete_with_file(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+
+        let dir = TempDir::new("delete");
+        let ids = db.insert_bulk_video_info(&test_videos()).await?;
+        let paths: Vec<PathBuf> = (1..=3).map(|i| dir.join(format!("{i}.mp4"))).collect();
+        for (id, path) in ids.iter().zip(&paths) {
+            std::fs::write(path, b"video")?;
+            db.mark_downloaded(*id, path, 5).await?;
+        }
+
+        let report = db.delete_video_info(ids[0], DeleteMode::RowOnly).await?;
+        assert_eq!(report.rows_deleted, 1);
+        assert_eq!(report.output_path.as_ref(), Some(&paths[0]));
+        assert!(paths[0].exists(), "The file is kept");
+
+        let report = db.delete_video_info(ids[1], DeleteMode::RowAndFile).await?;
+        assert_eq!(report.rows_deleted, 1);
+        assert!(!report.is_partial());
+        assert!(!paths[1].exists());
+
+        // A file that can't be removed doesn't keep the row
+        std::fs::remove_file(&paths[2])?;
+        std::fs::create_dir(&paths[2])?;
+        let report = db.delete_video_info(ids[2], DeleteMode::RowAndFile).await?;
+        assert_eq!(report.rows_deleted, 1);
+        assert!(report.is_partial());
+        assert!(db.fetch_one(ids[2]).await.is_err());
+
+        // Nor does a file that's already gone
+        let id = db.insert_video_info(&test_videos()[1]).await?;
+        db.mark_downloaded(id, &paths[1], 5).await?;
+        let report = db.delete_video_info(id, DeleteMode::RowAndFile).await?;
+        assert_eq!(report.rows_deleted, 1);
+        assert!(!report.is_partial());
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn delete_one_on_empty_db(pool: SqlitePool) {
         let db = Database { pool };
 
-        let deletions = db.delete_video_info(1).await.unwrap();
+        let deletions = db
+            .delete_video_info(1, DeleteMode::RowOnly)
+            .await
+            .unwrap()
+            .rows_deleted;
 
         assert_eq!(
             deletions, 0,
@@ -691,13 +1771,18 @@ mod tests {
     async fn insert_many_delete_nonexisting(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_video = &get_test_videos()[0];
+        let mut test_video = test_videos()[0].clone();
 
-        for _ in 0..4 {
-            db.insert_video_info(test_video).await.unwrap();
+        for i in 0..4 {
+            test_video.video_id = format!("id{i}");
+            db.insert_video_info(&test_video).await.unwrap();
         }
 
-        let deletions = db.delete_video_info(5).await.unwrap();
+        let deletions = db
+            .delete_video_info(5, DeleteMode::RowOnly)
+            .await
+            .unwrap()
+            .rows_deleted;
 
         assert_eq!(
             deletions, 0,
@@ -705,11 +1790,109 @@ mod tests {
         );
     }
 
+    #[sqlx::test]
+    async fn insert_duplicate(pool: SqlitePool) {
+        let db = Database { pool };
+
+        let test_videos = test_videos();
+        db.insert_video_info(&test_videos[0]).await.unwrap();
+
+        let error = db.insert_video_info(&test_videos[0]).await.unwrap_err();
+        assert!(matches!(error, Error::Duplicate), "{error:?}");
+
+        // Bulk inserts are all or nothing
+        assert!(db.insert_bulk_video_info(&test_videos).await.is_err());
+        let page = db.fetch_history(&HistoryQuery::new()).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+    }
+
+    #[sqlx::test]
+    async fn upsert(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+
+        let mut test_video = test_videos()[0].clone();
+        let id = db.upsert_video_info(&test_video).await?;
+        db.mark_downloaded(id, "/tmp/video.webm", 1024).await?;
+
+        test_video.title = "Video 1 (remastered)".to_string();
+        test_video.thumbnail = Some("https://i.ytimg.com/vi/id1/hq720.jpg".to_string());
+        test_video.video_formats.truncate(1);
+        assert_eq!(db.upsert_video_info(&test_video).await?, id);
+
+        let video = db.find_by_video_id("id1").await?.unwrap();
+        assert_eq!(video.get_id(), id);
+        assert_eq!(video.get_info(), &test_video);
+        assert_eq!(video.get_record().status, DownloadStatus::Downloaded);
+        assert_eq!(db.search("remastered", 10, 0).await?.len(), 1);
+
+        let other_id = db.upsert_video_info(&test_videos()[1]).await?;
+        assert_ne!(other_id, id);
+        assert!(db.find_by_video_id("id3").await?.is_none());
+
+        Ok(())
+    }
+
+    /// Videos added more than once are merged into the first one.
+    #[sqlx::test(migrations = false)]
+    async fn migrate_duplicates(pool: SqlitePool) -> Result<()> {
+        let migrator = migrate!();
+        let (old, new): (Vec<_>, Vec<_>) = migrator.iter().partition(|m| m.version < 8);
+        for migration in old {
+            pool.execute(&*migration.sql).await?;
+        }
+
+        pool.execute(
+            "INSERT INTO video_info
+                (video_id, title, author, duration_seconds, audio_available,
+                    added_at, downloaded_at, output_path, file_size, status)
+             VALUES
+                ('id1', 'Old title', 'Author 1', 1, 1, 100, 150, '/tmp/old.mp4', 10, 'downloaded'),
+                ('id2', 'Video 2', 'Author 2', 2, 1, 200, NULL, NULL, NULL, 'added'),
+                ('id1', 'New title', 'Author 1', 1, 1, 300, 350, '/tmp/new.mp4', 20, 'downloaded'),
+                ('id1', 'Newest title', 'Author 1', 1, 1, 400, NULL, NULL, NULL, 'failed');
+             INSERT INTO video_format (format_id, container, width, height, fps, video_info_id)
+             VALUES ('18', 'mp4', 640, 360, 25, 1), ('22', 'mp4', 1280, 720, 25, 4);
+             INSERT INTO download_queue
+                (container, width, height, fps, url, destination, paused, video_info_id)
+             VALUES ('mp4', 1280, 720, 25, NULL, '/tmp/video.mp4', 1, 3);",
+        )
+        .await?;
+
+        for migration in new {
+            pool.execute(&*migration.sql).await?;
+        }
+
+        let db = Database { pool };
+        let videos = db.fetch_history(&HistoryQuery::new()).await?.items;
+        assert_eq!(videos.len(), 2);
+
+        let video = db.find_by_video_id("id1").await?.unwrap();
+        assert_eq!(video.get_id(), 1);
+        assert_eq!(video.get_info().title, "Newest title");
+        assert_eq!(video.get_info().video_formats.len(), 1);
+        assert_eq!(video.get_info().video_formats[0].format_id, "22");
+        assert_eq!(
+            video.get_record().output_path,
+            Some(PathBuf::from("/tmp/new.mp4"))
+        );
+        assert_eq!(video.get_record().status, DownloadStatus::Downloaded);
+        assert_eq!(db.fetch_pending_downloads().await?[0].1.video_info_id, 1);
+        // The search index follows the merge
+        let found = db.search("title", 10, 0).await?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].get_info().title, "Newest title");
+
+        // New duplicates are rejected
+        assert!(db.insert_video_info(&test_videos()[1]).await.is_err());
+
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn delete_all(pool: SqlitePool) {
         let db = Database { pool };
 
-        let test_videos = get_test_videos();
+        let test_videos = test_videos();
         let ids = db.insert_bulk_video_info(&test_videos).await.unwrap();
 
         let rows_deleted = db.delete_all().await.unwrap();
@@ -720,4 +1903,133 @@ mod tests {
             "The number of deleted rows should be equal to the number of videos inserted"
         );
     }
+
+    #[sqlx::test]
+    async fn trash_restore_purge(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+
+        let test_videos = test_videos();
+        let ids = db.insert_bulk_video_info(&test_videos).await?;
+
+        assert_eq!(db.trash(ids[1]).await?, 1);
+        assert_eq!(db.trash(ids[1]).await?, 0, "Already in the trash");
+
+        // Trashed videos are out of the history and the search
+        let page = db.fetch_history(&HistoryQuery::new()).await?;
+        assert_eq!(page.items.len(), test_videos.len() - 1);
+        assert!(db.search(&test_videos[1].title, 10, 0).await?.is_empty());
+        assert!(db
+            .find_by_video_id(&test_videos[1].video_id)
+            .await?
+            .is_none());
+
+        // But can still be fetched on their own
+        let trashed = db.fetch_one(ids[1]).await?;
+        assert!(trashed.get_record().deleted_at.is_some());
+        let trash = db.fetch_trash().await?;
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].get_info(), &test_videos[1]);
+
+        assert_eq!(db.restore(ids[1]).await?, 1);
+        assert_eq!(db.restore(ids[1]).await?, 0, "Not in the trash anymore");
+        assert!(db.fetch_trash().await?.is_empty());
+        assert_eq!(db.fetch_one(ids[1]).await?.get_record().deleted_at, None);
+
+        // Adding a trashed video again takes it out of the trash
+        db.trash(ids[0]).await?;
+        assert_eq!(db.upsert_video_info(&test_videos[0]).await?, ids[0]);
+        assert!(db.fetch_trash().await?.is_empty());
+
+        assert_eq!(db.trash_all().await?, test_videos.len() as u64);
+        assert!(db
+            .fetch_history(&HistoryQuery::new())
+            .await?
+            .items
+            .is_empty());
+
+        // Only what was trashed long enough ago is purged
+        assert_eq!(db.purge_trash(Duration::from_secs(3600)).await?, 0);
+        assert_eq!(db.fetch_trash().await?.len(), test_videos.len());
+        assert_eq!(
+            db.purge_trash(Duration::ZERO).await?,
+            test_videos.len() as u64
+        );
+        assert!(db.fetch_trash().await?.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn pending_downloads(pool: SqlitePool) {
+        let db = Database { pool };
+
+        let ids = db.insert_bulk_video_info(&test_videos()).await.unwrap();
+
+        let mut pendings: Vec<PendingDownload> = ids
+            .iter()
+            .map(|id| PendingDownload {
+                video_info_id: *id,
+                format: VideoFormat {
+                    url: Some(format!("http://localhost/{id}")),
+                    ..test_videos()[0].video_formats[0].clone()
+                },
+                destination: format!("/tmp/{id}.webm").into(),
+                paused: false,
+                resume_offset: 0,
+            })
+            .collect();
+
+        let mut pending_ids = Vec::new();
+        for pending in &pendings {
+            pending_ids.push(db.insert_pending_download(pending).await.unwrap());
+        }
+
+        // Pause one, interrupt another, finish the last
+        assert_eq!(
+            db.set_pending_download_offset(pending_ids[0], 1234)
+                .await
+                .unwrap(),
+            1
+        );
+        pendings[0].resume_offset = 1234;
+        assert_eq!(
+            db.set_pending_download_paused(pending_ids[1], true)
+                .await
+                .unwrap(),
+            1
+        );
+        pendings[1].paused = true;
+        assert_eq!(db.delete_pending_download(pending_ids[2]).await.unwrap(), 1);
+        pendings.pop();
+        pending_ids.pop();
+
+        let db_pendings = db.fetch_pending_downloads().await.unwrap();
+        assert_eq!(
+            db_pendings,
+            pending_ids.into_iter().zip(pendings).collect::<Vec<_>>()
+        );
+    }
+
+    #[sqlx::test]
+    async fn pending_downloads_deleted_with_video(pool: SqlitePool) {
+        let db = Database { pool };
+
+        let id = db.insert_video_info(&test_videos()[0]).await.unwrap();
+        db.insert_pending_download(&PendingDownload {
+            video_info_id: id,
+            format: test_videos()[0].video_formats[0].clone(),
+            destination: "/tmp/video.webm".into(),
+            paused: false,
+            resume_offset: 0,
+        })
+        .await
+        .unwrap();
+
+        db.delete_video_info(id, DeleteMode::RowOnly).await.unwrap();
+
+        assert!(
+            db.fetch_pending_downloads().await.unwrap().is_empty(),
+            "Queued downloads of a deleted video should be deleted too"
+        );
+    }
 }
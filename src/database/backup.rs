@@ -0,0 +1,368 @@
+//! Copies of the history to fall back on if the database gets corrupted.
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use const_format::formatcp;
+use sqlx::{migrate, query, query_as, query_scalar, sqlite::SqliteRow, FromRow, Row, Sqlite};
+
+use super::{unix_seconds, Database};
+use crate::Result;
+
+/// Name of the directory the automatic backups are kept in, next to the
+/// database.
+const BACKUPS_DIR: &str = "backups";
+
+/// How many automatic backups are kept, the older ones are deleted.
+const MAX_BACKUPS: usize = 5;
+
+const MIGRATIONS_TABLE: &str = "_sqlx_migrations";
+
+/// What [check_integrity](Database::check_integrity) found wrong.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// What `PRAGMA integrity_check` reported, e.g. corrupted pages
+    pub problems: Vec<String>,
+    /// Rows referencing a row that doesn't exist
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty() && self.foreign_key_violations.is_empty()
+    }
+}
+
+/// A row of `table` referencing a row of `parent` that doesn't exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    /// `None` for the tables without rowid
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+impl FromRow<'_, SqliteRow> for ForeignKeyViolation {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            table: row.try_get("table")?,
+            rowid: row.try_get("rowid")?,
+            parent: row.try_get("parent")?,
+        })
+    }
+}
+
+impl Database<Sqlite> {
+    /// Write a copy of the database to a new file at `path`, while it's in
+    /// use. The copy is compacted, as with `VACUUM`.
+    ///
+    /// # Errors
+    /// Fails if there's already a file at `path`.
+    pub async fn backup_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        query("VACUUM INTO $1")
+            .bind(path.as_ref().to_string_lossy())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Look for corruption and rows referencing deleted rows.
+    pub async fn check_integrity(&self) -> Result<IntegrityReport> {
+        let problems: Vec<String> = query_scalar("PRAGMA integrity_check")
+            .fetch_all(&self.pool)
+            .await?;
+        let foreign_key_violations = query_as("PRAGMA foreign_key_check")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(IntegrityReport {
+            problems: problems.into_iter().filter(|row| row != "ok").collect(),
+            foreign_key_violations,
+        })
+    }
+
+    /// Whether some of the migrations haven't been applied yet, to a
+    /// database that already has some.
+    pub(super) async fn needs_migrations(&self) -> Result<bool> {
+        const QUERY_HAS_TABLE: &str = formatcp!(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '{MIGRATIONS_TABLE}'"
+        );
+        const QUERY_LATEST: &str =
+            formatcp!("SELECT MAX(version) FROM {MIGRATIONS_TABLE} WHERE success");
+
+        let has_table: i64 = query_scalar(QUERY_HAS_TABLE).fetch_one(&self.pool).await?;
+        if has_table == 0 {
+            return Ok(false);
+        }
+        let latest: Option<i64> = query_scalar(QUERY_LATEST).fetch_one(&self.pool).await?;
+        let newest = migrate!().iter().map(|migration| migration.version).max();
+
+        Ok(latest < newest)
+    }
+
+    /// Back up the database at `db_path` to its [backups directory](Self::backups_dir),
+    /// keeping only the most recent backups.
+    pub(super) async fn backup_rotating(&self, db_path: &Path) -> Result<PathBuf> {
+        let dir = Self::backups_dir(db_path);
+        std::fs::create_dir_all(&dir)?;
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let backup = dir.join(format!("{}-{millis}.db", file_stem(db_path)));
+
+        self.backup_to(&backup).await?;
+        rotate(&dir, &file_stem(db_path), MAX_BACKUPS)?;
+
+        Ok(backup)
+    }
+
+    /// Where the automatic backups of the database at `db_path` are kept.
+    pub fn backups_dir(db_path: impl AsRef<Path>) -> PathBuf {
+        let db_path = db_path.as_ref();
+        db_path.parent().unwrap_or(Path::new(".")).join(BACKUPS_DIR)
+    }
+
+    /// The most recent automatic backup of the database at `db_path`, if any.
+    pub fn latest_backup(db_path: impl AsRef<Path>) -> Option<PathBuf> {
+        let dir = Self::backups_dir(&db_path);
+        let backups = list_backups(&dir, &file_stem(db_path.as_ref())).ok()?;
+        backups.into_iter().last()
+    }
+
+    /// Replace the database at `db_path` with the `backup`, after
+    /// [setting it aside](Self::set_aside).
+    ///
+    /// The database must not be open.
+    pub fn restore_backup(backup: impl AsRef<Path>, db_path: impl AsRef<Path>) -> io::Result<()> {
+        Self::set_aside(&db_path)?;
+        std::fs::copy(backup, db_path)?;
+
+        Ok(())
+    }
+
+    /// Rename the database at `db_path` to end with `.broken-<seconds>`, so
+    /// that a new one is created in its place while it can still be salvaged.
+    ///
+    /// The database must not be open.
+    pub fn set_aside(db_path: impl AsRef<Path>) -> io::Result<()> {
+        let suffix = format!(".broken-{}", unix_seconds(SystemTime::now()));
+
+        // Along with its journal, which would be applied to the new database
+        for extension in ["", "-journal", "-wal", "-shm"] {
+            let mut path = db_path.as_ref().as_os_str().to_owned();
+            path.push(extension);
+            let mut broken = path.clone();
+            broken.push(&suffix);
+            match std::fs::rename(&path, broken) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `history` for `/data/history.db`.
+fn file_stem(db_path: &Path) -> String {
+    db_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The backups named after `stem` in `dir`, the oldest first.
+fn list_backups(dir: &Path, stem: &str) -> io::Result<Vec<PathBuf>> {
+    let prefix = format!("{stem}-");
+    let mut backups: Vec<(u128, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let millis = path
+                .file_stem()?
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .parse()
+                .ok()?;
+            (path.extension()? == "db").then_some((millis, path))
+        })
+        .collect();
+    backups.sort();
+
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Delete the oldest backups named after `stem` in `dir` so that only `keep`
+/// remain.
+fn rotate(dir: &Path, stem: &str, keep: usize) -> io::Result<()> {
+    let backups = list_backups(dir, stem)?;
+    for old in &backups[..backups.len().saturating_sub(keep)] {
+        std::fs::remove_file(old)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+    use sqlx::{Executor, SqlitePool};
+
+    use super::{rotate, ForeignKeyViolation, MAX_BACKUPS};
+    use crate::{database::Database, test_util::TempDir, video::VideoInfo};
+
+    #[sqlx::test]
+    async fn backup_to(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+        let dir = TempDir::new("backup_to");
+        let video_info = VideoInfo::placeholder("dQw4w9WgXcQ");
+        let id = db.insert_video_info(&video_info).await?;
+
+        let backup = dir.join("backup.db");
+        db.backup_to(&backup).await?;
+        assert!(db.backup_to(&backup).await.is_err(), "Never overwritten");
+
+        let restored = Database::init_with_filename(&backup).await?;
+        assert_eq!(restored.fetch_one(id).await?.get_info(), &video_info);
+        restored.close().await;
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn check_integrity(pool: SqlitePool) -> Result<()> {
+        let db = Database { pool };
+        db.insert_video_info(&VideoInfo::placeholder("dQw4w9WgXcQ"))
+            .await?;
+        assert!(db.check_integrity().await?.is_ok());
+
+        let mut conn = db.pool.acquire().await?;
+        conn.execute("PRAGMA foreign_keys = OFF").await?;
+        conn.execute(
+            "INSERT INTO video_format (container, width, height, fps, video_info_id)
+             VALUES ('mp4', 640, 360, 30, 42)",
+        )
+        .await?;
+        conn.execute("PRAGMA foreign_keys = ON").await?;
+        drop(conn);
+
+        let report = db.check_integrity().await?;
+        assert!(!report.is_ok());
+        assert!(report.problems.is_empty());
+        assert_eq!(
+            report.foreign_key_violations,
+            vec![ForeignKeyViolation {
+                table: "video_format".to_string(),
+                rowid: Some(1),
+                parent: "video_info".to_string(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn backup_before_migrations_and_restore() -> Result<()> {
+        let dir = TempDir::new("backup_before_migrations");
+        let path = dir.join("history.db");
+
+        // A database left by the previous version
+        let db = Database::init_with_filename(&path).await?;
+        let id = db
+            .insert_video_info(&VideoInfo::placeholder("dQw4w9WgXcQ"))
+            .await?;
+        db.pool
+            .execute(
+                "DROP INDEX video_info_deleted_at;
+                 ALTER TABLE video_info DROP COLUMN deleted_at;
+                 DELETE FROM _sqlx_migrations WHERE version = 9;",
+            )
+            .await?;
+        db.close().await;
+        assert_eq!(Database::latest_backup(&path), None);
+
+        let db = Database::init_with_filename(&path).await?;
+        let backup = Database::latest_backup(&path).expect("backed up before migrating");
+        assert!(backup.starts_with(dir.join("backups")));
+        db.close().await;
+
+        // Up to date, nothing to back up
+        let db = Database::init_with_filename(&path).await?;
+        assert_eq!(Database::latest_backup(&path), Some(backup.clone()));
+        db.close().await;
+
+        fs::write(&path, "not a database")?;
+        assert!(Database::init_with_filename(&path).await.is_err());
+
+        Database::restore_backup(&backup, &path)?;
+        let db = Database::init_with_filename(&path).await?;
+        assert!(db.fetch_one(id).await.is_ok());
+        db.close().await;
+        let broken = fs::read_dir(dir.path())?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().contains(".broken-"))
+            .count();
+        assert_eq!(broken, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrate_when_backup_fails() -> Result<()> {
+        let dir = TempDir::new("migrate_when_backup_fails");
+        let path = dir.join("history.db");
+
+        let db = Database::init_with_filename(&path).await?;
+        db.pool
+            .execute(
+                "DROP INDEX video_info_deleted_at;
+                 ALTER TABLE video_info DROP COLUMN deleted_at;
+                 DELETE FROM _sqlx_migrations WHERE version = 9;",
+            )
+            .await?;
+        db.close().await;
+        // The backups directory can't be created
+        fs::write(Database::backups_dir(&path), "not a directory")?;
+
+        let db = Database::init_with_filename(&path).await?;
+        assert!(!db.needs_migrations().await?);
+        assert_eq!(Database::latest_backup(&path), None);
+        db.close().await;
+
+        Ok(())
+    }
+
+    #[test]
+    fn rotate_backups() -> Result<()> {
+        let dir = TempDir::new("rotate_backups");
+        for millis in [900, 1000, 1100, 1200, 1300, 1400, 1500] {
+            fs::write(dir.join(format!("history-{millis}.db")), "")?;
+        }
+        fs::write(dir.join("other-100.db"), "")?;
+
+        rotate(dir.path(), "history", MAX_BACKUPS)?;
+
+        let mut left: Vec<String> = fs::read_dir(dir.path())?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_>>()?;
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                "history-1100.db",
+                "history-1200.db",
+                "history-1300.db",
+                "history-1400.db",
+                "history-1500.db",
+                "other-100.db"
+            ]
+        );
+
+        Ok(())
+    }
+}
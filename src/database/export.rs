@@ -0,0 +1,231 @@
+//! Writes the history in formats other tools can read.
+use std::{io::Write, path::Path, time::SystemTime};
+
+use sqlx::Sqlite;
+
+use super::{
+    history::{Direction, HistoryQuery, SortKey},
+    unix_seconds, Database,
+};
+use crate::{
+    video::{watch_url, DownloadStatus, ManagedVideo},
+    Result,
+};
+
+/// What [Database::export] writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// An array of [VideoInfo](crate::video::VideoInfo)'s along with their
+    /// formats
+    Json,
+    /// A row per video, without the formats but with where it was downloaded
+    Csv,
+    /// A URL per line, the batch file read by `yt-dlp -a`
+    UrlList,
+}
+
+impl ExportFormat {
+    /// The file extension usually given to the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::UrlList => "txt",
+        }
+    }
+}
+
+/// How many videos are fetched from the database at a time.
+const EXPORT_PAGE: u32 = 500;
+
+const CSV_HEADER: [&str; 13] = [
+    "video_id",
+    "url",
+    "title",
+    "author",
+    "duration_seconds",
+    "thumbnail",
+    "audio_available",
+    "added_at",
+    "status",
+    "downloaded_at",
+    "output_path",
+    "file_size",
+    "chosen_format_id",
+];
+
+impl Database<Sqlite> {
+    /// Write every video of the history to `writer` as `format`, the oldest
+    /// first. Returns how many videos were written.
+    ///
+    /// Times are written in seconds since the Unix epoch.
+    pub async fn export(&self, format: ExportFormat, mut writer: impl Write) -> Result<usize> {
+        let query = HistoryQuery::new()
+            .sort_by(SortKey::AddedAt, Direction::Ascending)
+            .limit(EXPORT_PAGE);
+
+        match format {
+            ExportFormat::Json => writer.write_all(b"[")?,
+            ExportFormat::Csv => write_csv_row(&mut writer, CSV_HEADER)?,
+            ExportFormat::UrlList => {}
+        }
+
+        let mut exported = 0;
+        let mut page = self.fetch_history(&query).await?;
+        loop {
+            for video in &page.items {
+                match format {
+                    ExportFormat::Json => {
+                        if exported > 0 {
+                            writer.write_all(b",")?;
+                        }
+                        writer.write_all(b"\n")?;
+                        serde_json::to_writer(&mut writer, video.get_info())?;
+                    }
+                    ExportFormat::Csv => write_csv_row(&mut writer, csv_fields(video))?,
+                    ExportFormat::UrlList => {
+                        writeln!(writer, "{}", watch_url(&video.get_info().video_id))?
+                    }
+                }
+                exported += 1;
+            }
+
+            let Some(cursor) = page.next_cursor else {
+                break;
+            };
+            page = self.fetch_history(&query.clone().after(cursor)).await?;
+        }
+
+        if format == ExportFormat::Json {
+            writer.write_all(b"\n]\n")?;
+        }
+        writer.flush()?;
+
+        Ok(exported)
+    }
+}
+
+fn csv_fields(video: &ManagedVideo) -> [String; 13] {
+    let info = video.get_info();
+    let record = video.get_record();
+    let seconds = |time: Option<SystemTime>| time.map(unix_seconds).map(|s| s.to_string());
+    let status = match record.status {
+        DownloadStatus::Added => "added",
+        DownloadStatus::Downloaded => "downloaded",
+        DownloadStatus::Failed => "failed",
+    };
+
+    [
+        info.video_id.clone(),
+        watch_url(&info.video_id),
+        info.title.clone(),
+        info.author.clone(),
+        info.duration.as_secs().to_string(),
+        info.thumbnail.clone().unwrap_or_default(),
+        info.audio_available.to_string(),
+        unix_seconds(record.added_at).to_string(),
+        status.to_string(),
+        seconds(record.downloaded_at).unwrap_or_default(),
+        record
+            .output_path
+            .as_deref()
+            .map(Path::to_string_lossy)
+            .unwrap_or_default()
+            .into_owned(),
+        record.file_size.map(|s| s.to_string()).unwrap_or_default(),
+        record.chosen_format_id.clone().unwrap_or_default(),
+    ]
+}
+
+/// Write `fields` as a line of RFC 4180 CSV.
+fn write_csv_row<S: AsRef<str>>(
+    writer: &mut impl Write,
+    fields: impl IntoIterator<Item = S>,
+) -> std::io::Result<()> {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        let field = field.as_ref();
+        if field.contains([',', '"', '\n', '\r']) {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            writer.write_all(field.as_bytes())?;
+        }
+    }
+    writer.write_all(b"\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+
+    use super::ExportFormat;
+    use crate::{database::Database, test_util::youtube_videos, video::VideoInfo};
+
+    async fn export(db: &Database<sqlx::Sqlite>, format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        let exported = db.export(format, &mut out).await.unwrap();
+        assert_eq!(exported, 2);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[sqlx::test]
+    async fn export_json(pool: SqlitePool) {
+        let db = Database { pool };
+        let test_videos = youtube_videos();
+        db.insert_bulk_video_info(&test_videos).await.unwrap();
+
+        let json = export(&db, ExportFormat::Json).await;
+
+        assert!(json.contains(r#""duration_seconds":212"#), "{json}");
+        assert!(!json.contains(r#""url""#), "Expiring links aren't exported");
+        let videos: Vec<VideoInfo> = serde_json::from_str(&json).unwrap();
+        assert_eq!(videos, test_videos);
+    }
+
+    #[sqlx::test]
+    async fn export_csv(pool: SqlitePool) {
+        let db = Database { pool };
+        let ids = db.insert_bulk_video_info(&youtube_videos()).await.unwrap();
+        db.mark_downloaded(ids[0], "/videos/rick, astley.mp4", 13_375_498)
+            .await
+            .unwrap();
+
+        let csv = export(&db, ExportFormat::Csv).await;
+        let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("video_id,url,title,author,duration_seconds,"));
+        assert!(lines[1].starts_with(
+            "dQw4w9WgXcQ,https://www.youtube.com/watch?v=dQw4w9WgXcQ,Never Gonna Give You Up,Rick Astley,212,"
+        ));
+        assert!(lines[1].contains(",downloaded,"));
+        assert!(lines[1].ends_with(r#","/videos/rick, astley.mp4",13375498,"#));
+        assert!(lines[2].starts_with(
+            r#"jNQXAC9IVRw,https://www.youtube.com/watch?v=jNQXAC9IVRw,"Me at the ""zoo"", again",jawed,19,,false,"#
+        ));
+        assert!(lines[2].ends_with(",added,,,,"));
+    }
+
+    #[sqlx::test]
+    async fn export_url_list(pool: SqlitePool) {
+        let db = Database { pool };
+        db.insert_bulk_video_info(&youtube_videos()).await.unwrap();
+
+        assert_eq!(
+            export(&db, ExportFormat::UrlList).await,
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ\nhttps://www.youtube.com/watch?v=jNQXAC9IVRw\n"
+        );
+    }
+
+    #[sqlx::test]
+    async fn export_empty(pool: SqlitePool) {
+        let db = Database { pool };
+
+        let mut out = Vec::new();
+        assert_eq!(db.export(ExportFormat::Json, &mut out).await.unwrap(), 0);
+        let videos: Vec<VideoInfo> = serde_json::from_slice(&out).unwrap();
+        assert!(videos.is_empty());
+    }
+}
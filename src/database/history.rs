@@ -0,0 +1,592 @@
+//! Filtered and sorted queries over the history of videos.
+use std::time::SystemTime;
+
+use const_format::formatcp;
+use sqlx::{sqlite::SqliteRow, FromRow, QueryBuilder, Row, Sqlite};
+
+use super::{
+    unix_seconds, Database, IdAndInfo, ADDED_AT, AUDIO_AVAILABLE, AUTHOR, CONTAINER, DELETED_AT,
+    DURATION_SECONDS, HEIGHT, ID, IS_AUDIO_ONLY, RECORD_COLUMNS, THUMBNAIL, TITLE, VIDEO_FORMAT,
+    VIDEO_ID, VIDEO_INFO, VIDEO_INFO_ID,
+};
+use crate::{video::ManagedVideo, Result};
+
+/// What the videos are sorted by. Videos with equal keys are sorted by row id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Ignoring case
+    Title,
+    /// Ignoring case
+    Author,
+    Duration,
+    #[default]
+    AddedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+/// A video of a [Page] that the next or previous page starts from,
+/// without including it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryCursor {
+    key: SortValue,
+    id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SortValue {
+    Text(String),
+    Integer(i64),
+}
+
+/// A page of the items matching a query, in the order of the query
+/// whichever direction it was fetched in.
+///
+/// The cursors are keys rather than offsets, so the pages neither skip nor
+/// repeat items when some are added or deleted in between.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass it to [HistoryQuery::after] to fetch the following page.
+    /// `None` if there are no items after this page.
+    pub next_cursor: Option<HistoryCursor>,
+    /// Pass it to [HistoryQuery::before] to fetch the preceding page.
+    /// `None` if there are no items before this page.
+    pub prev_cursor: Option<HistoryCursor>,
+    /// Whether there are more items past this page in the direction it was
+    /// fetched in, i.e. after it unless fetched [before](HistoryQuery::before)
+    /// a cursor.
+    pub has_more: bool,
+}
+
+/// Which side of a [HistoryCursor] a page is fetched from.
+#[derive(Debug, Clone, PartialEq)]
+enum Position {
+    After(HistoryCursor),
+    Before(HistoryCursor),
+}
+
+/// Filters and sorting of [Database::fetch_history], most recently added
+/// first by default.
+///
+/// # Example
+/// ```no_run
+/// # use yd_gui::{database::{history::*, *}, Result};
+/// # use sqlx::Sqlite;
+/// #
+/// # async fn example(db: Database<Sqlite>) -> Result<()> {
+/// // 720p or better mp4 videos with audio, by title
+/// let query = HistoryQuery::new()
+///     .audio_available(true)
+///     .container("mp4")
+///     .min_height(720)
+///     .sort_by(SortKey::Title, Direction::Ascending);
+///
+/// let first = db.fetch_history(&query).await?;
+/// if let Some(cursor) = first.next_cursor {
+///     let second = db.fetch_history(&query.clone().after(cursor)).await?;
+///
+///     // Back to the first page
+///     if let Some(cursor) = second.prev_cursor {
+///         db.fetch_history(&query.before(cursor)).await?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryQuery {
+    author: Option<String>,
+    audio_available: Option<bool>,
+    container: Option<String>,
+    min_height: Option<u32>,
+    max_height: Option<u32>,
+    added_after: Option<SystemTime>,
+    added_before: Option<SystemTime>,
+    sort_key: SortKey,
+    direction: Direction,
+    limit: u32,
+    position: Option<Position>,
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            author: None,
+            audio_available: None,
+            container: None,
+            min_height: None,
+            max_height: None,
+            added_after: None,
+            added_before: None,
+            sort_key: SortKey::default(),
+            direction: Direction::default(),
+            limit: 20,
+            position: None,
+        }
+    }
+}
+
+impl HistoryQuery {
+    /// All the videos, 20 at a time, most recently added first.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only the videos by `author`, ignoring case.
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Only the videos with or without audio.
+    pub fn audio_available(mut self, audio_available: bool) -> Self {
+        self.audio_available = Some(audio_available);
+        self
+    }
+
+    /// Only the videos with a format in `container`, e.g. `"mp4"`.
+    ///
+    /// Combined with the height filters, a single format must match all of them.
+    pub fn container(mut self, container: impl Into<String>) -> Self {
+        self.container = Some(container.into());
+        self
+    }
+
+    /// Only the videos with a format at least `height` pixels high.
+    pub fn min_height(mut self, height: u32) -> Self {
+        self.min_height = Some(height);
+        self
+    }
+
+    /// Only the videos with a format at most `height` pixels high.
+    pub fn max_height(mut self, height: u32) -> Self {
+        self.max_height = Some(height);
+        self
+    }
+
+    /// Only the videos added at or after `time`.
+    pub fn added_after(mut self, time: SystemTime) -> Self {
+        self.added_after = Some(time);
+        self
+    }
+
+    /// Only the videos added before `time`.
+    pub fn added_before(mut self, time: SystemTime) -> Self {
+        self.added_before = Some(time);
+        self
+    }
+
+    pub fn sort_by(mut self, key: SortKey, direction: Direction) -> Self {
+        self.sort_key = key;
+        self.direction = direction;
+        self
+    }
+
+    /// Fetch pages of at most `limit` videos.
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Fetch the page following the [next_cursor](Page::next_cursor) of a page.
+    pub fn after(mut self, cursor: HistoryCursor) -> Self {
+        self.position = Some(Position::After(cursor));
+        self
+    }
+
+    /// Fetch the page preceding the [prev_cursor](Page::prev_cursor) of a page.
+    pub fn before(mut self, cursor: HistoryCursor) -> Self {
+        self.position = Some(Position::Before(cursor));
+        self
+    }
+
+    fn is_backward(&self) -> bool {
+        matches!(self.position, Some(Position::Before(_)))
+    }
+
+    fn sort_column(&self) -> &'static str {
+        match self.sort_key {
+            SortKey::Title => formatcp!("{TITLE} COLLATE NOCASE"),
+            SortKey::Author => formatcp!("{AUTHOR} COLLATE NOCASE"),
+            SortKey::Duration => DURATION_SECONDS,
+            SortKey::AddedAt => ADDED_AT,
+        }
+    }
+
+    /// The SQL of the query, with every value bound as a parameter.
+    /// Pages fetched backward are selected in reverse order, and one more
+    /// video than the limit is selected to know if there are more.
+    fn build(&self) -> QueryBuilder<'_, Sqlite> {
+        let sort_column = self.sort_column();
+        let mut builder = QueryBuilder::new(formatcp!(
+            "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
+                {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}, "
+        ));
+        builder.push(sort_column);
+        builder.push(formatcp!(
+            " AS {SORT_VALUE} FROM {VIDEO_INFO} WHERE {DELETED_AT} IS NULL"
+        ));
+
+        if let Some(author) = &self.author {
+            builder.push(formatcp!(" AND {AUTHOR} = "));
+            builder.push_bind(author);
+            builder.push(" COLLATE NOCASE");
+        }
+        if let Some(audio_available) = self.audio_available {
+            builder.push(formatcp!(" AND {AUDIO_AVAILABLE} = "));
+            builder.push_bind(audio_available);
+        }
+        if self.container.is_some() || self.min_height.is_some() || self.max_height.is_some() {
+            builder.push(formatcp!(
+                " AND EXISTS (SELECT 1 FROM {VIDEO_FORMAT}
+                    WHERE {VIDEO_FORMAT}.{VIDEO_INFO_ID} = {VIDEO_INFO}.{ID}"
+            ));
+            if let Some(container) = &self.container {
+                builder.push(formatcp!(" AND {CONTAINER} = "));
+                builder.push_bind(container);
+            }
+            if self.min_height.is_some() || self.max_height.is_some() {
+                builder.push(formatcp!(" AND NOT {IS_AUDIO_ONLY}"));
+            }
+            if let Some(min_height) = self.min_height {
+                builder.push(formatcp!(" AND {HEIGHT} >= "));
+                builder.push_bind(min_height);
+            }
+            if let Some(max_height) = self.max_height {
+                builder.push(formatcp!(" AND {HEIGHT} <= "));
+                builder.push_bind(max_height);
+            }
+            builder.push(")");
+        }
+        if let Some(added_after) = self.added_after {
+            builder.push(formatcp!(" AND {ADDED_AT} >= "));
+            builder.push_bind(unix_seconds(added_after));
+        }
+        if let Some(added_before) = self.added_before {
+            builder.push(formatcp!(" AND {ADDED_AT} < "));
+            builder.push_bind(unix_seconds(added_before));
+        }
+
+        let (comparison, order) = match (self.direction, self.is_backward()) {
+            (Direction::Ascending, false) | (Direction::Descending, true) => (">", "ASC"),
+            (Direction::Descending, false) | (Direction::Ascending, true) => ("<", "DESC"),
+        };
+        if let Some(
+            Position::After(HistoryCursor { key, id })
+            | Position::Before(HistoryCursor { key, id }),
+        ) = &self.position
+        {
+            builder.push(format!(" AND ({sort_column} {comparison} "));
+            key.push_bind(&mut builder);
+            builder.push(format!(" OR ({sort_column} = "));
+            key.push_bind(&mut builder);
+            builder.push(format!(" AND {ID} {comparison} "));
+            builder.push_bind(*id);
+            builder.push("))");
+        }
+
+        builder.push(format!(
+            " ORDER BY {sort_column} {order}, {ID} {order} LIMIT "
+        ));
+        builder.push_bind(self.limit as i64 + 1);
+
+        builder
+    }
+}
+
+const SORT_VALUE: &str = "sort_value";
+
+impl SortValue {
+    fn push_bind(&self, builder: &mut QueryBuilder<'_, Sqlite>) {
+        match self {
+            SortValue::Text(text) => builder.push_bind(text.clone()),
+            SortValue::Integer(integer) => builder.push_bind(*integer),
+        };
+    }
+}
+
+struct VideoAndKey(IdAndInfo, SortValue);
+impl FromRow<'_, SqliteRow> for VideoAndKey {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        let key = match row.try_get::<String, _>(SORT_VALUE) {
+            Ok(text) => SortValue::Text(text),
+            Err(_) => SortValue::Integer(row.try_get(SORT_VALUE)?),
+        };
+        Ok(Self(IdAndInfo::from_row(row)?, key))
+    }
+}
+
+impl Database<Sqlite> {
+    /// Fetch a page of the videos matching `query`.
+    pub async fn fetch_history(&self, query: &HistoryQuery) -> Result<Page<ManagedVideo>> {
+        let mut rows: Vec<VideoAndKey> =
+            query.build().build_query_as().fetch_all(&self.pool).await?;
+
+        let has_more = rows.len() > query.limit as usize;
+        rows.truncate(query.limit as usize);
+        if query.is_backward() {
+            rows.reverse();
+        }
+
+        let cursor = |VideoAndKey(IdAndInfo(id, ..), key): &VideoAndKey| HistoryCursor {
+            key: key.clone(),
+            id: *id,
+        };
+        let first = rows.first().map(cursor);
+        let last = rows.last().map(cursor);
+        // The cursor a page was fetched from is on the other side of it
+        let (next_cursor, prev_cursor) = match query.position {
+            None => (last.filter(|_| has_more), None),
+            Some(Position::After(_)) => (last.filter(|_| has_more), first),
+            Some(Position::Before(_)) => (last, first.filter(|_| has_more)),
+        };
+
+        let id_and_infos = rows
+            .into_iter()
+            .map(|VideoAndKey(id_and_info, _)| id_and_info)
+            .collect();
+        let items = self.with_formats(id_and_infos).await?;
+
+        Ok(Page {
+            items,
+            next_cursor,
+            prev_cursor,
+            has_more,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use sqlx::{query, SqlitePool};
+
+    use super::{Direction, HistoryQuery, Page, SortKey};
+    use crate::{
+        database::{Database, DeleteMode},
+        test_util::VideoInfoBuilder,
+        video::{ManagedVideo, VideoFormat, VideoInfo},
+    };
+
+    fn video(title: &str, author: &str, seconds: u64, formats: &[(&str, u32)]) -> VideoInfo {
+        VideoInfoBuilder::new(0)
+            .video_id(title.to_lowercase())
+            .title(title)
+            .author(author)
+            .duration_secs(seconds)
+            .formats(
+                formats
+                    .iter()
+                    .map(|&(container, height)| VideoFormat {
+                        container: container.to_string(),
+                        width: height * 16 / 9,
+                        height,
+                        is_audio_only: height == 0,
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+            .audio_available(formats.iter().any(|&(_, height)| height == 0))
+            .build()
+    }
+
+    /// Insert test videos, added a day apart from the 1st of January 2024.
+    async fn seed(db: &Database<sqlx::Sqlite>) -> Vec<i32> {
+        let videos = vec![
+            video("Banana", "Chef", 300, &[("mp4", 1080), ("m4a", 0)]),
+            video("apple", "chef", 60, &[("webm", 720)]),
+            video("Cherry", "Gardener", 300, &[("mp4", 360), ("webm", 2160)]),
+            video("date", "Gardener", 120, &[("webm", 480), ("webm", 0)]),
+        ];
+        let ids = db.insert_bulk_video_info(&videos).await.unwrap();
+        for (day, id) in ids.iter().enumerate() {
+            query("UPDATE video_info SET added_at = $1 WHERE id = $2")
+                .bind(1_704_067_200 + day as i64 * 86_400)
+                .bind(id)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        ids
+    }
+
+    async fn titles(db: &Database<sqlx::Sqlite>, query: &HistoryQuery) -> Vec<String> {
+        db.fetch_history(query)
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|video| video.get_info().title.clone())
+            .collect()
+    }
+
+    #[sqlx::test]
+    async fn sort(pool: SqlitePool) {
+        let db = Database { pool };
+        seed(&db).await;
+
+        let by = |key, direction| HistoryQuery::new().sort_by(key, direction);
+
+        assert_eq!(
+            titles(&db, &HistoryQuery::new()).await,
+            ["date", "Cherry", "apple", "Banana"]
+        );
+        assert_eq!(
+            titles(&db, &by(SortKey::AddedAt, Direction::Ascending)).await,
+            ["Banana", "apple", "Cherry", "date"]
+        );
+        assert_eq!(
+            titles(&db, &by(SortKey::Title, Direction::Ascending)).await,
+            ["apple", "Banana", "Cherry", "date"]
+        );
+        // Ties are sorted by id
+        assert_eq!(
+            titles(&db, &by(SortKey::Author, Direction::Descending)).await,
+            ["date", "Cherry", "apple", "Banana"]
+        );
+        assert_eq!(
+            titles(&db, &by(SortKey::Duration, Direction::Ascending)).await,
+            ["apple", "date", "Banana", "Cherry"]
+        );
+    }
+
+    #[sqlx::test]
+    async fn filters(pool: SqlitePool) {
+        let db = Database { pool };
+        seed(&db).await;
+        let by_title = HistoryQuery::new().sort_by(SortKey::Title, Direction::Ascending);
+
+        assert_eq!(
+            titles(&db, &by_title.clone().author("CHEF")).await,
+            ["apple", "Banana"]
+        );
+        assert_eq!(
+            titles(&db, &by_title.clone().audio_available(true)).await,
+            ["Banana", "date"]
+        );
+        assert_eq!(
+            titles(&db, &by_title.clone().container("webm")).await,
+            ["apple", "Cherry", "date"]
+        );
+        // Audio only formats have no height
+        assert_eq!(
+            titles(&db, &by_title.clone().max_height(480)).await,
+            ["Cherry", "date"]
+        );
+        // A single format must match every format filter
+        assert_eq!(
+            titles(&db, &by_title.clone().container("mp4").min_height(720)).await,
+            ["Banana"]
+        );
+        assert_eq!(
+            titles(&db, &by_title.clone().min_height(500).max_height(1080)).await,
+            ["apple", "Banana"]
+        );
+
+        let day = |n: u64| UNIX_EPOCH + Duration::from_secs(1_704_067_200 + n * 86_400);
+        assert_eq!(
+            titles(
+                &db,
+                &by_title.clone().added_after(day(1)).added_before(day(3))
+            )
+            .await,
+            ["apple", "Cherry"]
+        );
+
+        // Values are bound, not interpolated
+        assert!(titles(&db, &by_title.author("' OR 1 = 1 --"))
+            .await
+            .is_empty());
+    }
+
+    fn page_titles(page: &Page<ManagedVideo>) -> Vec<&str> {
+        page.items
+            .iter()
+            .map(|video| video.get_info().title.as_str())
+            .collect()
+    }
+
+    #[sqlx::test]
+    async fn keyset_pagination(pool: SqlitePool) {
+        let db = Database { pool };
+        seed(&db).await;
+
+        // Two videos last 300s, the pages must not skip or repeat one
+        let query = HistoryQuery::new()
+            .sort_by(SortKey::Duration, Direction::Descending)
+            .limit(1);
+        let mut titles = Vec::new();
+        let mut page = db.fetch_history(&query).await.unwrap();
+        assert_eq!(page.prev_cursor, None);
+        loop {
+            titles.extend(page_titles(&page).into_iter().map(str::to_string));
+            let Some(cursor) = page.next_cursor else {
+                assert!(!page.has_more);
+                break;
+            };
+            assert!(page.has_more);
+            page = db
+                .fetch_history(&query.clone().after(cursor))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(titles, ["Cherry", "Banana", "date", "apple"]);
+    }
+
+    #[sqlx::test]
+    async fn pages_in_both_directions(pool: SqlitePool) {
+        let db = Database { pool };
+        let ids = seed(&db).await;
+        let query = HistoryQuery::new()
+            .sort_by(SortKey::Title, Direction::Ascending)
+            .limit(2);
+
+        let first = db.fetch_history(&query).await.unwrap();
+        assert_eq!(page_titles(&first), ["apple", "Banana"]);
+        assert!(first.has_more);
+
+        let second = db
+            .fetch_history(&query.clone().after(first.next_cursor.unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(page_titles(&second), ["Cherry", "date"]);
+        assert!(!second.has_more);
+        assert_eq!(second.next_cursor, None);
+
+        // Back to the first page, in the same order
+        let back = db
+            .fetch_history(&query.clone().before(second.prev_cursor.clone().unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(page_titles(&back), ["apple", "Banana"]);
+        assert!(!back.has_more);
+        assert_eq!(back.prev_cursor, None);
+        assert!(back.next_cursor.is_some());
+
+        // Deleting the videos around a cursor leaves no gap or duplicate
+        db.delete_video_info(ids[0], DeleteMode::RowOnly)
+            .await
+            .unwrap(); // Banana
+        db.delete_video_info(ids[2], DeleteMode::RowOnly)
+            .await
+            .unwrap(); // Cherry
+        let back = db
+            .fetch_history(&query.clone().before(second.prev_cursor.unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(page_titles(&back), ["apple"]);
+        let forward = db
+            .fetch_history(&query.after(back.next_cursor.unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(page_titles(&forward), ["date"]);
+    }
+}
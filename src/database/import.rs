@@ -0,0 +1,541 @@
+//! Reads videos into the history from the files of other machines and tools.
+use std::{collections::HashSet, io::Read, mem, str::FromStr, time::Duration};
+
+use const_format::formatcp;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{query_as, Sqlite};
+
+use super::{
+    Database, IdAndInfo, Inserted, OnExisting, AUDIO_AVAILABLE, AUTHOR, DELETED_AT,
+    DURATION_SECONDS, ID, RECORD_COLUMNS, THUMBNAIL, TITLE, VIDEO_ID, VIDEO_INFO,
+};
+use crate::{
+    video::{parse_url, ManagedVideo, VideoId, VideoInfo},
+    Result,
+};
+
+/// What [Database::import] reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// An array of [VideoInfo]'s, as [exported](super::export::ExportFormat::Json)
+    Json,
+    /// A header row and a row per video. Only the `video_id` or `url` column
+    /// is required, among those [exported](super::export::ExportFormat::Csv).
+    Csv,
+    /// The `youtube <id>` lines yt-dlp writes to its `--download-archive`.
+    /// Lines that are URLs, as in a batch file, are accepted too.
+    DownloadArchive,
+}
+
+/// What [Database::import] did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    /// Row ids of the imported videos
+    pub imported: Vec<i32>,
+    /// Row ids of the videos that were in the trash, and are taken out of it
+    pub restored: Vec<i32>,
+    /// Ids of the videos skipped because they are in the history already,
+    /// or earlier in the input
+    pub duplicates: Vec<String>,
+    /// The entries skipped because they couldn't be read
+    pub errors: Vec<LineError>,
+}
+
+/// An entry of the input that couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("line {line}: {message}")]
+pub struct LineError {
+    /// Where the entry starts, from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl LineError {
+    fn new(line: usize, message: impl ToString) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Entries read from the input along with the line they start at
+type Entries = Vec<(usize, VideoInfo)>;
+
+impl Database<Sqlite> {
+    /// Read the videos of `reader` as `format` and insert those that aren't
+    /// in the history yet, all at once. Those in the trash are taken out of it.
+    ///
+    /// The videos of a [DownloadArchive](ImportFormat::DownloadArchive), and
+    /// those without a title, are inserted as [placeholders](VideoInfo::placeholder)
+    /// whose metadata is to be fetched later,
+    /// see [fetch_placeholders](Self::fetch_placeholders).
+    ///
+    /// # Errors
+    /// Fails without importing anything if `reader` fails, isn't UTF-8, or
+    /// the videos can't be inserted.
+    pub async fn import(
+        &self,
+        format: ImportFormat,
+        mut reader: impl Read,
+    ) -> Result<ImportReport> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let (entries, errors) = match format {
+            ImportFormat::Json => parse_json(&text),
+            ImportFormat::Csv => parse_csv(&text),
+            ImportFormat::DownloadArchive => parse_archive(&text),
+        };
+
+        // Only the first of the entries with the same id is inserted
+        let mut seen = HashSet::new();
+        let first: Vec<bool> = entries
+            .iter()
+            .map(|(_, video)| seen.insert(video.video_id.clone()))
+            .collect();
+        let videos: Vec<VideoInfo> = entries
+            .iter()
+            .zip(&first)
+            .filter(|(_, &first)| first)
+            .map(|((_, video), _)| video.clone())
+            .collect();
+        let mut inserted = self
+            .insert_bulk_video_info_with(&videos, OnExisting::SkipOrRestore)
+            .await?
+            .into_iter();
+
+        let mut report = ImportReport {
+            errors,
+            ..Default::default()
+        };
+        for ((_, video), first) in entries.into_iter().zip(first) {
+            // Those after the first are duplicates
+            let outcome = if first { inserted.next() } else { None };
+            match outcome {
+                Some(Inserted::New(id)) => report.imported.push(id),
+                Some(Inserted::Restored(id)) => report.restored.push(id),
+                Some(Inserted::Skipped) | None => report.duplicates.push(video.video_id),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Fetch at most `limit` videos whose metadata is unknown, the oldest
+    /// first. Refresh them with [upsert_video_info](Self::upsert_video_info).
+    pub async fn fetch_placeholders(&self, limit: u32) -> Result<Vec<ManagedVideo>> {
+        const QUERY: &str = formatcp!(
+            "SELECT {ID}, {VIDEO_ID}, {TITLE}, {AUTHOR},
+                {DURATION_SECONDS}, {THUMBNAIL}, {AUDIO_AVAILABLE}, {RECORD_COLUMNS}
+             FROM {VIDEO_INFO}
+             WHERE {TITLE} = '' AND {DELETED_AT} IS NULL
+             ORDER BY {ID} ASC
+             LIMIT $1
+            "
+        );
+        let id_and_infos: Vec<IdAndInfo> =
+            query_as(QUERY).bind(limit).fetch_all(&self.pool).await?;
+
+        self.with_formats(id_and_infos).await
+    }
+}
+
+/// The line of the byte at `offset` of `text`.
+fn line_at(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+/// The entries are parsed one by one so that an invalid one doesn't prevent
+/// reading the others.
+fn parse_json(text: &str) -> (Entries, Vec<LineError>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    let start = text.len() - text.trim_start().len();
+    if !text[start..].starts_with('[') {
+        errors.push(LineError::new(
+            line_at(text, start),
+            "expected a JSON array",
+        ));
+        return (entries, errors);
+    }
+    let mut offset = start + 1;
+    loop {
+        offset += text[offset..].len() - text[offset..].trim_start().len();
+        if text[offset..].starts_with(']') {
+            break;
+        }
+
+        let line = line_at(text, offset);
+        let mut values = serde_json::Deserializer::from_str(&text[offset..]).into_iter::<Value>();
+        match values.next() {
+            Some(Ok(value)) => match VideoInfo::deserialize(value) {
+                Ok(video) => match VideoId::from_str(&video.video_id) {
+                    Ok(_) => entries.push((line, video)),
+                    Err(e) => errors.push(LineError::new(line, e)),
+                },
+                Err(e) => errors.push(LineError::new(line, e)),
+            },
+            // Where the next entry starts is unknown
+            Some(Err(e)) => {
+                errors.push(LineError::new(line, e));
+                break;
+            }
+            None => {
+                errors.push(LineError::new(line, "unterminated JSON array"));
+                break;
+            }
+        }
+        offset += values.byte_offset();
+
+        offset += text[offset..].len() - text[offset..].trim_start().len();
+        if text[offset..].starts_with(',') {
+            offset += 1;
+        } else if !text[offset..].starts_with(']') {
+            errors.push(LineError::new(line_at(text, offset), "expected `,` or `]`"));
+            break;
+        }
+    }
+
+    (entries, errors)
+}
+
+fn parse_csv(text: &str) -> (Entries, Vec<LineError>) {
+    let mut entries = Vec::new();
+    let (records, mut errors) = csv_records(text);
+    let mut records = records.into_iter();
+
+    let Some((_, header)) = records.next() else {
+        return (entries, errors);
+    };
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let video_id = column("video_id");
+    let url = column("url");
+    let title = column("title");
+    let author = column("author");
+    let duration = column("duration_seconds");
+    let thumbnail = column("thumbnail");
+    let audio_available = column("audio_available");
+    if video_id.is_none() && url.is_none() {
+        errors.insert(0, LineError::new(1, "no `video_id` or `url` column"));
+        return (entries, errors);
+    }
+
+    for (line, fields) in records {
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| fields.get(column))
+                .map(|field| field.trim())
+                .filter(|field| !field.is_empty())
+        };
+        let parse = || -> std::result::Result<VideoInfo, String> {
+            let video_id = match (field(video_id), field(url)) {
+                (Some(video_id), _) => VideoId::from_str(video_id).map_err(|e| e.to_string())?,
+                (None, Some(url)) => parse_url(url).map_err(|e| e.to_string())?.video_id,
+                (None, None) => return Err("no video id or URL".to_string()),
+            };
+            let duration = match field(duration) {
+                Some(seconds) => seconds
+                    .parse()
+                    .map_err(|_| format!("invalid duration_seconds `{seconds}`"))?,
+                None => 0,
+            };
+            let audio_available = match field(audio_available) {
+                Some("true" | "1") => true,
+                Some("false" | "0") | None => false,
+                Some(other) => return Err(format!("invalid audio_available `{other}`")),
+            };
+
+            Ok(VideoInfo {
+                title: field(title).unwrap_or_default().to_string(),
+                author: field(author).unwrap_or_default().to_string(),
+                duration: Duration::from_secs(duration),
+                thumbnail: field(thumbnail).map(str::to_string),
+                audio_available,
+                ..VideoInfo::placeholder(video_id)
+            })
+        };
+        match parse() {
+            Ok(video) => entries.push((line, video)),
+            Err(message) => errors.push(LineError::new(line, message)),
+        }
+    }
+    errors.sort_by_key(|error| error.line);
+
+    (entries, errors)
+}
+
+/// Split RFC 4180 CSV into records, each along with the line it starts at.
+/// Blank lines are skipped.
+fn csv_records(text: &str) -> (Vec<(usize, Vec<String>)>, Vec<LineError>) {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut start_line = 1;
+
+    let mut end_record = |fields: &mut Vec<String>, field: &mut String, start_line| {
+        fields.push(mem::take(field));
+        let record = mem::take(fields);
+        if record.len() > 1 || !record[0].trim().is_empty() {
+            records.push((start_line, record));
+        }
+    };
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (in_quotes, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => in_quotes = false,
+            (true, c) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            (false, '"') => in_quotes = true,
+            (false, ',') => fields.push(mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                end_record(&mut fields, &mut field, start_line);
+                line += 1;
+                start_line = line;
+            }
+            (false, c) => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        let error = LineError::new(start_line, "unterminated quoted field");
+        return (records, vec![error]);
+    }
+    end_record(&mut fields, &mut field, start_line);
+
+    (records, Vec::new())
+}
+
+fn parse_archive(text: &str) -> (Entries, Vec<LineError>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, content) in text.lines().enumerate() {
+        let line = i + 1;
+        let content = content.trim();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+
+        let video_id = match content.split_once(char::is_whitespace) {
+            Some((extractor, id)) if extractor.eq_ignore_ascii_case("youtube") => {
+                VideoId::from_str(id.trim()).map_err(|e| e.to_string())
+            }
+            Some((extractor, _)) if !extractor.contains(['/', '.']) => {
+                Err(format!("unsupported extractor `{extractor}`"))
+            }
+            _ => parse_url(content)
+                .map(|url| url.video_id)
+                .map_err(|e| e.to_string()),
+        };
+        match video_id {
+            Ok(video_id) => entries.push((line, VideoInfo::placeholder(video_id))),
+            Err(message) => errors.push(LineError::new(line, message)),
+        }
+    }
+
+    (entries, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::SqlitePool;
+
+    use super::{ImportFormat, ImportReport, LineError};
+    use crate::{
+        database::{
+            export::ExportFormat,
+            history::{Direction, HistoryQuery, SortKey},
+            Database,
+        },
+        test_util::youtube_videos,
+        video::VideoInfo,
+    };
+
+    async fn import(db: &Database<sqlx::Sqlite>, format: ImportFormat, text: &str) -> ImportReport {
+        db.import(format, text.as_bytes()).await.unwrap()
+    }
+
+    #[sqlx::test]
+    async fn import_exported(pool: SqlitePool) {
+        let db = Database { pool };
+        let test_videos = youtube_videos();
+        db.insert_bulk_video_info(&test_videos).await.unwrap();
+
+        for (export, import_as) in [
+            (ExportFormat::Json, ImportFormat::Json),
+            (ExportFormat::Csv, ImportFormat::Csv),
+        ] {
+            let mut out = Vec::new();
+            db.export(export, &mut out).await.unwrap();
+
+            // Everything is a duplicate until the history is cleared
+            let report = db.import(import_as, out.as_slice()).await.unwrap();
+            assert!(report.imported.is_empty());
+            assert_eq!(report.duplicates, ["dQw4w9WgXcQ", "jNQXAC9IVRw"]);
+
+            db.delete_all().await.unwrap();
+            let report = db.import(import_as, out.as_slice()).await.unwrap();
+            assert_eq!(report.imported.len(), 2);
+            assert!(report.errors.is_empty(), "{:?}", report.errors);
+
+            let oldest_first = HistoryQuery::new().sort_by(SortKey::AddedAt, Direction::Ascending);
+            let imported: Vec<VideoInfo> = db
+                .fetch_history(&oldest_first)
+                .await
+                .unwrap()
+                .items
+                .into_iter()
+                .map(VideoInfo::from)
+                .collect();
+            match import_as {
+                ImportFormat::Json => assert_eq!(imported, test_videos),
+                // The formats aren't exported as CSV
+                _ => {
+                    let mut without_formats = test_videos.clone();
+                    without_formats[0].video_formats.clear();
+                    assert_eq!(imported, without_formats);
+                }
+            }
+        }
+    }
+
+    #[sqlx::test]
+    async fn json_errors(pool: SqlitePool) {
+        let db = Database { pool };
+        let json = r#"[
+            {"video_id": "dQw4w9WgXcQ", "title": "Rick", "author": "Rick Astley",
+                "duration_seconds": 212, "thumbnail": null, "audio_available": true},
+            {"video_id": "jNQXAC9IVRw", "title": "Zoo"},
+            {"video_id": "not an id", "title": "Bad", "author": "", "duration_seconds": 0,
+                "thumbnail": null, "audio_available": false},
+            {"video_id": "dQw4w9WgXcQ", "title": "Rick again", "author": "Rick Astley",
+                "duration_seconds": 212, "thumbnail": null, "audio_available": true}
+        ]"#;
+
+        let report = import(&db, ImportFormat::Json, json).await;
+
+        assert_eq!(report.imported.len(), 1);
+        assert_eq!(report.duplicates, ["dQw4w9WgXcQ"]);
+        let lines: Vec<usize> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [4, 5]);
+        assert!(report.errors[0].message.contains("author"));
+
+        // Invalid JSON stops the import at the entry
+        let report = import(&db, ImportFormat::Json, "[\n{\"video_id\": \"x\",\n}\n]").await;
+        assert!(report.imported.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].line, 2);
+
+        let report = import(&db, ImportFormat::Json, "{}").await;
+        assert_eq!(report.errors, [LineError::new(1, "expected a JSON array")]);
+    }
+
+    #[sqlx::test]
+    async fn csv_errors(pool: SqlitePool) {
+        let db = Database { pool };
+        let csv = "url,title,duration_seconds\r\n\
+            https://youtu.be/dQw4w9WgXcQ,\"Never Gonna\nGive You Up\",212\r\n\
+            \r\n\
+            https://youtu.be/jNQXAC9IVRw,Zoo,nineteen\r\n\
+            https://example.com/video,Other,1\r\n\
+            https://www.youtube.com/watch?v=dQw4w9WgXcQ,Again,212\r\n\
+            ,Nothing,0\r\n";
+
+        let report = import(&db, ImportFormat::Csv, csv).await;
+
+        assert_eq!(report.imported.len(), 1);
+        let video = db.fetch_one(report.imported[0]).await.unwrap();
+        assert_eq!(video.get_info().title, "Never Gonna\nGive You Up");
+        assert_eq!(video.get_info().duration, Duration::from_secs(212));
+        assert_eq!(report.duplicates, ["dQw4w9WgXcQ"]);
+        let lines: Vec<usize> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [5, 6, 8]);
+        assert_eq!(
+            report.errors[0].message,
+            "invalid duration_seconds `nineteen`"
+        );
+
+        let report = import(&db, ImportFormat::Csv, "title\r\nZoo\r\n").await;
+        assert_eq!(
+            report.errors,
+            [LineError::new(1, "no `video_id` or `url` column")]
+        );
+
+        let report = import(&db, ImportFormat::Csv, "video_id\njNQXAC9IVRw\n\"oops\n").await;
+        assert_eq!(report.imported.len(), 1);
+        assert_eq!(
+            report.errors,
+            [LineError::new(3, "unterminated quoted field")]
+        );
+    }
+
+    #[sqlx::test]
+    async fn import_trashed(pool: SqlitePool) {
+        let db = Database { pool };
+        let test_videos = youtube_videos();
+        let ids = db.insert_bulk_video_info(&test_videos).await.unwrap();
+        db.trash(ids[1]).await.unwrap();
+
+        let archive = "youtube dQw4w9WgXcQ\nyoutube jNQXAC9IVRw\nyoutube jNQXAC9IVRw\n";
+        let report = import(&db, ImportFormat::DownloadArchive, archive).await;
+
+        assert!(report.imported.is_empty());
+        assert_eq!(report.restored, [ids[1]]);
+        assert_eq!(report.duplicates, ["dQw4w9WgXcQ", "jNQXAC9IVRw"]);
+
+        // Back in the history as it was before being trashed
+        assert!(db.fetch_trash().await.unwrap().is_empty());
+        let video = db.find_by_video_id("jNQXAC9IVRw").await.unwrap().unwrap();
+        assert_eq!(video.get_info(), &test_videos[1]);
+    }
+
+    #[sqlx::test]
+    async fn download_archive(pool: SqlitePool) {
+        let db = Database { pool };
+        db.insert_video_info(&youtube_videos()[1]).await.unwrap();
+        let archive = "youtube dQw4w9WgXcQ\n\
+            \n\
+            youtube jNQXAC9IVRw\n\
+            vimeo 76979871\n\
+            youtube tooshort\n\
+            https://www.youtube.com/watch?v=9bZkp7q19f0\n\
+            youtube dQw4w9WgXcQ\n";
+
+        let report = import(&db, ImportFormat::DownloadArchive, archive).await;
+
+        assert_eq!(report.imported.len(), 2);
+        assert_eq!(report.duplicates, ["jNQXAC9IVRw", "dQw4w9WgXcQ"]);
+        let lines: Vec<usize> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [4, 5]);
+        assert_eq!(report.errors[0].message, "unsupported extractor `vimeo`");
+
+        // Their metadata is to be fetched
+        let placeholders = db.fetch_placeholders(10).await.unwrap();
+        let ids: Vec<&str> = placeholders
+            .iter()
+            .map(|video| video.get_info().video_id.as_str())
+            .collect();
+        assert_eq!(ids, ["dQw4w9WgXcQ", "9bZkp7q19f0"]);
+        assert!(placeholders[0].get_info().is_placeholder());
+
+        db.upsert_video_info(&youtube_videos()[0]).await.unwrap();
+        assert_eq!(db.fetch_placeholders(10).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,215 @@
+//! Finds where the history database is kept.
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+
+use sqlx::Sqlite;
+
+use super::{Database, PoolOptions};
+use crate::{paths, settings::Settings, Result};
+
+/// Environment variable overriding the path of the history database.
+pub const DB_ENV_VAR: &str = "YD_GUI_DB";
+
+/// Name of the history database file in its default directory.
+const FILE_NAME: &str = "history.db";
+
+/// Where the history database is and what decided it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbLocation {
+    pub path: PathBuf,
+    pub source: DbSource,
+}
+
+/// The settings [locate](Database::locate) goes through, the first one set
+/// wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbSource {
+    /// The `--db` command line flag
+    Flag,
+    /// The [DB_ENV_VAR] environment variable
+    Env,
+    /// The [db_path](Settings::db_path) of the settings file
+    Settings,
+    /// `history.db` in the [data directory](paths::data_dir)
+    Default,
+}
+
+impl DbSource {
+    /// e.g. `the YD_GUI_DB environment variable`
+    pub fn describe(&self) -> &'static str {
+        match self {
+            DbSource::Flag => "the --db flag",
+            DbSource::Env => "the YD_GUI_DB environment variable",
+            DbSource::Settings => "the settings file",
+            DbSource::Default => "the default location",
+        }
+    }
+}
+
+impl Database<Sqlite> {
+    /// Find where the history database is: at the `flag` path given on the
+    /// command line, else in the [DB_ENV_VAR] environment variable, else in
+    /// the [Settings], else `history.db` in the [data directory](paths::data_dir).
+    ///
+    /// A settings file that can't be read is ignored with a warning.
+    ///
+    /// # Errors
+    /// Fails if it comes down to the default location and there is no home
+    /// directory.
+    pub fn locate(flag: Option<PathBuf>) -> io::Result<DbLocation> {
+        let env = std::env::var_os(DB_ENV_VAR).filter(|env| !env.is_empty());
+        // Only read when needed, so that a broken file can be worked around
+        let settings = match flag.is_none() && env.is_none() {
+            true => Settings::load_or_default(),
+            false => Settings::default(),
+        };
+
+        resolve(flag, env, settings, paths::data_dir()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no home directory to keep the history database in",
+            )
+        })
+    }
+
+    /// Initialize the database at the `location` with the connections set up
+    /// as `options`, creating its directory if needed.
+    ///
+    /// At the [default location](DbSource::Default), the database previous
+    /// versions kept next to the executable is moved there the first time.
+    pub async fn init_at(location: &DbLocation, options: PoolOptions) -> Result<Self> {
+        if location.source == DbSource::Default {
+            if let Some(legacy) = legacy_path() {
+                migrate_legacy(&legacy, &location.path)?;
+            }
+        }
+        if let Some(dir) = location.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        Self::init_with_options(&location.path, options).await
+    }
+}
+
+/// The first path set among the `flag`, the `env` variable, the `settings`
+/// and the `data_dir`.
+fn resolve(
+    flag: Option<PathBuf>,
+    env: Option<OsString>,
+    settings: Settings,
+    data_dir: Option<PathBuf>,
+) -> Option<DbLocation> {
+    let located = |path, source| Some(DbLocation { path, source });
+    let env = env.filter(|env| !env.is_empty()).map(PathBuf::from);
+
+    if let Some(path) = flag {
+        located(path, DbSource::Flag)
+    } else if let Some(path) = env {
+        located(path, DbSource::Env)
+    } else if let Some(path) = settings.db_path {
+        located(path, DbSource::Settings)
+    } else {
+        located(data_dir?.join(FILE_NAME), DbSource::Default)
+    }
+}
+
+/// `history.db` next to the executable, where it used to be.
+fn legacy_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(FILE_NAME))
+}
+
+/// Move the database at `legacy` to `path` unless there's already one there.
+/// When `legacy` can't be removed, e.g. on a read-only install, it's copied
+/// and left behind, which is fine since `path` is then used from now on.
+///
+/// Returns whether there was a database to move.
+fn migrate_legacy(legacy: &Path, path: &Path) -> io::Result<bool> {
+    if path.exists() || !legacy.is_file() {
+        return Ok(false);
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    if std::fs::rename(legacy, path).is_err() {
+        std::fs::copy(legacy, path)?;
+        let _ = std::fs::remove_file(legacy);
+    }
+    tracing::info!(
+        "moved the history database from {} to {}",
+        legacy.display(),
+        path.display()
+    );
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::{migrate_legacy, resolve, DbLocation, DbSource};
+    use crate::{settings::Settings, test_util::TempDir};
+
+    #[test]
+    fn resolution_chain() {
+        let flag = || Some(PathBuf::from("/flag.db"));
+        let env = || Some("/env.db".into());
+        let settings = || Settings {
+            db_path: Some(PathBuf::from("/settings.db")),
+            ..Default::default()
+        };
+        let data_dir = || Some(PathBuf::from("/home/user/.local/share/yd-gui"));
+        let located = |path: &str, source| {
+            Some(DbLocation {
+                path: PathBuf::from(path),
+                source,
+            })
+        };
+
+        assert_eq!(
+            resolve(flag(), env(), settings(), data_dir()),
+            located("/flag.db", DbSource::Flag)
+        );
+        assert_eq!(
+            resolve(None, env(), settings(), data_dir()),
+            located("/env.db", DbSource::Env)
+        );
+        assert_eq!(
+            resolve(None, Some("".into()), settings(), data_dir()),
+            located("/settings.db", DbSource::Settings),
+            "An empty variable is unset"
+        );
+        assert_eq!(
+            resolve(None, None, Settings::default(), data_dir()),
+            located(
+                "/home/user/.local/share/yd-gui/history.db",
+                DbSource::Default
+            )
+        );
+        assert_eq!(resolve(None, None, Settings::default(), None), None);
+    }
+
+    #[test]
+    fn migrate_legacy_once() {
+        let dir = TempDir::new("legacy");
+        let legacy = dir.join("history.db");
+        let path = dir.join("data/yd-gui/history.db");
+
+        assert!(!migrate_legacy(&legacy, &path).unwrap(), "Nothing to move");
+
+        fs::write(&legacy, b"legacy").unwrap();
+        assert!(migrate_legacy(&legacy, &path).unwrap());
+        assert!(!legacy.exists());
+        assert_eq!(fs::read(&path).unwrap(), b"legacy");
+
+        // An existing database is never overwritten
+        fs::write(&legacy, b"older").unwrap();
+        assert!(!migrate_legacy(&legacy, &path).unwrap());
+        assert_eq!(fs::read(&path).unwrap(), b"legacy");
+    }
+}
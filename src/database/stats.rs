@@ -0,0 +1,291 @@
+//! Aggregate numbers about the videos of the history.
+use std::{
+    ops::{Bound, RangeBounds},
+    time::{Duration, SystemTime},
+};
+
+use const_format::formatcp;
+use sqlx::{query_as, Sqlite};
+
+use super::{
+    unix_seconds, Database, ADDED_AT, AUTHOR, CHOSEN_FORMAT_ID, CONTAINER, DELETED_AT,
+    DOWNLOADED_AT, DURATION_SECONDS, FILE_SIZE, FORMAT_ID, HEIGHT, ID, IS_AUDIO_ONLY, STATUS,
+    VIDEO_FORMAT, VIDEO_INFO, VIDEO_INFO_ID,
+};
+use crate::{video::DownloadStatus, Result};
+
+/// How many authors [LibraryStats::top_authors] lists at most.
+pub const MAX_TOP_AUTHORS: u32 = 10;
+
+/// Numbers about the videos of the history, leaving out the trash.
+///
+/// The videos are counted in the range of [stats](Database::stats) by when
+/// they were added, and the downloads by when they were downloaded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LibraryStats {
+    /// Videos added to the history
+    pub videos: u64,
+    /// Videos downloaded
+    pub downloads: u64,
+    /// Total size of the downloaded files
+    pub bytes_downloaded: u64,
+    /// Total length of the downloaded videos
+    pub duration_downloaded: Duration,
+    /// The authors with the most videos added and how many, the most first
+    pub top_authors: Vec<(String, u64)>,
+    /// How many downloads were in each container, the most first
+    pub containers: Vec<(String, u64)>,
+    /// How many downloads were of each height, the highest first and `None`
+    /// for the audio only ones
+    pub resolutions: Vec<(Option<u32>, u64)>,
+    /// How many downloads there were on each day, as `YYYY-MM-DD` in UTC,
+    /// the oldest first. The days without downloads are left out.
+    pub downloads_per_day: Vec<(String, u64)>,
+}
+
+/// Rows added in the range bound as `$1..$2`, in seconds.
+const ADDED_IN_RANGE: &str =
+    formatcp!("{DELETED_AT} IS NULL AND {ADDED_AT} >= $1 AND {ADDED_AT} < $2");
+
+/// Rows downloaded in the range bound as `$1..$2`, in seconds, with their
+/// status bound as `$3`.
+const DOWNLOADED_IN_RANGE: &str = formatcp!(
+    "{DELETED_AT} IS NULL AND {STATUS} = $3 AND {DOWNLOADED_AT} >= $1 AND {DOWNLOADED_AT} < $2"
+);
+
+/// The downloaded format of the downloads, those without one are left out.
+const JOIN_CHOSEN_FORMAT: &str = formatcp!(
+    "{VIDEO_INFO} JOIN {VIDEO_FORMAT}
+        ON {VIDEO_FORMAT}.{VIDEO_INFO_ID} = {VIDEO_INFO}.{ID}
+        AND {VIDEO_FORMAT}.{FORMAT_ID} = {VIDEO_INFO}.{CHOSEN_FORMAT_ID}"
+);
+
+impl Database<Sqlite> {
+    /// Compute the [LibraryStats] of the videos added or downloaded in
+    /// `range`, e.g. `..` for all of them or `since..` for the recent ones.
+    pub async fn stats(&self, range: impl RangeBounds<SystemTime>) -> Result<LibraryStats> {
+        const QUERY_TOTALS: &str = formatcp!(
+            "SELECT
+                (SELECT COUNT(*) FROM {VIDEO_INFO} WHERE {ADDED_IN_RANGE}),
+                COUNT(*),
+                COALESCE(SUM({FILE_SIZE}), 0),
+                COALESCE(SUM({DURATION_SECONDS}), 0)
+             FROM {VIDEO_INFO}
+             WHERE {DOWNLOADED_IN_RANGE}
+            "
+        );
+        const QUERY_TOP_AUTHORS: &str = formatcp!(
+            "SELECT {AUTHOR}, COUNT(*) AS count
+             FROM {VIDEO_INFO}
+             WHERE {ADDED_IN_RANGE}
+             GROUP BY {AUTHOR}
+             ORDER BY count DESC, {AUTHOR}
+             LIMIT $3
+            "
+        );
+        const QUERY_CONTAINERS: &str = formatcp!(
+            "SELECT {VIDEO_FORMAT}.{CONTAINER}, COUNT(*) AS count
+             FROM {JOIN_CHOSEN_FORMAT}
+             WHERE {DOWNLOADED_IN_RANGE}
+             GROUP BY {VIDEO_FORMAT}.{CONTAINER}
+             ORDER BY count DESC, {VIDEO_FORMAT}.{CONTAINER}
+            "
+        );
+        const QUERY_RESOLUTIONS: &str = formatcp!(
+            "SELECT
+                CASE WHEN {VIDEO_FORMAT}.{IS_AUDIO_ONLY} THEN NULL
+                    ELSE {VIDEO_FORMAT}.{HEIGHT} END AS resolution,
+                COUNT(*)
+             FROM {JOIN_CHOSEN_FORMAT}
+             WHERE {DOWNLOADED_IN_RANGE}
+             GROUP BY resolution
+             ORDER BY resolution DESC NULLS LAST
+            "
+        );
+        const QUERY_PER_DAY: &str = formatcp!(
+            "SELECT date({DOWNLOADED_AT}, 'unixepoch') AS day, COUNT(*)
+             FROM {VIDEO_INFO}
+             WHERE {DOWNLOADED_IN_RANGE}
+             GROUP BY day
+             ORDER BY day
+            "
+        );
+        let (start, end) = seconds_range(range);
+
+        let (videos, downloads, bytes_downloaded, seconds_downloaded): (i64, i64, i64, i64) =
+            query_as(QUERY_TOTALS)
+                .bind(start)
+                .bind(end)
+                .bind(DownloadStatus::Downloaded)
+                .fetch_one(&self.pool)
+                .await?;
+        let top_authors: Vec<(String, i64)> = query_as(QUERY_TOP_AUTHORS)
+            .bind(start)
+            .bind(end)
+            .bind(MAX_TOP_AUTHORS)
+            .fetch_all(&self.pool)
+            .await?;
+        let containers: Vec<(String, i64)> = query_as(QUERY_CONTAINERS)
+            .bind(start)
+            .bind(end)
+            .bind(DownloadStatus::Downloaded)
+            .fetch_all(&self.pool)
+            .await?;
+        let resolutions: Vec<(Option<u32>, i64)> = query_as(QUERY_RESOLUTIONS)
+            .bind(start)
+            .bind(end)
+            .bind(DownloadStatus::Downloaded)
+            .fetch_all(&self.pool)
+            .await?;
+        let downloads_per_day: Vec<(String, i64)> = query_as(QUERY_PER_DAY)
+            .bind(start)
+            .bind(end)
+            .bind(DownloadStatus::Downloaded)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(LibraryStats {
+            videos: videos as u64,
+            downloads: downloads as u64,
+            bytes_downloaded: bytes_downloaded as u64,
+            duration_downloaded: Duration::from_secs(seconds_downloaded as u64),
+            top_authors: counts(top_authors),
+            containers: counts(containers),
+            resolutions: counts(resolutions),
+            downloads_per_day: counts(downloads_per_day),
+        })
+    }
+}
+
+/// The `range` as Unix seconds, the start included and the end excluded.
+fn seconds_range(range: impl RangeBounds<SystemTime>) -> (i64, i64) {
+    let start = match range.start_bound() {
+        Bound::Included(time) => unix_seconds(*time),
+        Bound::Excluded(time) => unix_seconds(*time) + 1,
+        Bound::Unbounded => i64::MIN,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(time) => unix_seconds(*time) + 1,
+        Bound::Excluded(time) => unix_seconds(*time),
+        Bound::Unbounded => i64::MAX,
+    };
+    (start, end)
+}
+
+/// SQLite counts as `i64`.
+fn counts<K>(rows: Vec<(K, i64)>) -> Vec<(K, u64)> {
+    rows.into_iter()
+        .map(|(key, count)| (key, count as u64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use sqlx::{query, SqlitePool};
+
+    use super::LibraryStats;
+    use crate::{
+        database::Database,
+        test_util::VideoInfoBuilder,
+        video::{VideoFormat, VideoInfo},
+    };
+
+    fn video(video_id: &str, author: &str, seconds: u64) -> VideoInfo {
+        let format = |format_id: &str, container: &str, height| VideoFormat {
+            format_id: format_id.to_string(),
+            container: container.to_string(),
+            height,
+            is_audio_only: height == 0,
+            ..Default::default()
+        };
+        VideoInfoBuilder::new(0)
+            .video_id(video_id)
+            .title(video_id)
+            .author(author)
+            .duration_secs(seconds)
+            .formats(vec![
+                format("22", "mp4", 720),
+                format("137", "mp4", 1080),
+                format("248", "webm", 1080),
+                format("140", "m4a", 0),
+            ])
+            .build()
+    }
+
+    #[sqlx::test]
+    async fn stats(pool: SqlitePool) {
+        let db = Database { pool };
+        let videos = vec![
+            video("a", "Chef", 600),
+            video("b", "Gardener", 300),
+            video("c", "Chef", 120),
+            video("d", "Gardener", 60),
+            video("e", "Chef", 30),
+        ];
+        let ids = db.insert_bulk_video_info(&videos).await.unwrap();
+        // Added a day apart from the 1st of January 2024, and downloaded the
+        // day after in the chosen format
+        let day = |n: i64| 1_704_067_200 + n * 86_400;
+        let downloads = [(ids[0], "137", 1), (ids[1], "248", 1), (ids[2], "140", 3)];
+        for (n, id) in ids.iter().enumerate() {
+            query("UPDATE video_info SET added_at = $1 WHERE id = $2")
+                .bind(day(n as i64))
+                .bind(id)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        for (id, format_id, n) in downloads {
+            db.set_chosen_format_id(id, format_id).await.unwrap();
+            db.mark_downloaded(id, "/videos/video.mp4", 1000)
+                .await
+                .unwrap();
+            query("UPDATE video_info SET downloaded_at = $1 WHERE id = $2")
+                .bind(day(n))
+                .bind(id)
+                .execute(&db.pool)
+                .await
+                .unwrap();
+        }
+        // Neither counted when trashed
+        db.trash(ids[4]).await.unwrap();
+
+        assert_eq!(
+            db.stats(..).await.unwrap(),
+            LibraryStats {
+                videos: 4,
+                downloads: 3,
+                bytes_downloaded: 3000,
+                duration_downloaded: Duration::from_secs(1020),
+                top_authors: vec![("Chef".to_string(), 2), ("Gardener".to_string(), 2)],
+                containers: vec![
+                    ("m4a".to_string(), 1),
+                    ("mp4".to_string(), 1),
+                    ("webm".to_string(), 1)
+                ],
+                resolutions: vec![(Some(1080), 2), (None, 1)],
+                downloads_per_day: vec![
+                    ("2024-01-02".to_string(), 2),
+                    ("2024-01-04".to_string(), 1)
+                ],
+            }
+        );
+
+        // From the 2nd of January 2024 included to the 4th excluded
+        let at = |n| UNIX_EPOCH + Duration::from_secs(day(n) as u64);
+        let stats = db.stats(at(1)..at(3)).await.unwrap();
+        assert_eq!(stats.videos, 2);
+        assert_eq!(stats.downloads, 2);
+        assert_eq!(
+            stats.top_authors,
+            [("Chef".to_string(), 1), ("Gardener".to_string(), 1)]
+        );
+        assert_eq!(stats.downloads_per_day, [("2024-01-02".to_string(), 2)]);
+        assert_eq!(db.stats(at(3)..=at(3)).await.unwrap().downloads, 1);
+
+        let empty = db.stats(..at(0)).await.unwrap();
+        assert_eq!(empty, LibraryStats::default());
+    }
+}
@@ -0,0 +1,642 @@
+//! Downloads videos and keeps track of the ones being downloaded.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use reqwest::{
+    header::{CONTENT_RANGE, RANGE},
+    Client, StatusCode,
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::broadcast,
+};
+
+use crate::{
+    video::{ManagedVideo, VideoFormat, VideoInfo},
+    Error, Result,
+};
+use progress::{ProgressEvent, ProgressTracker};
+
+pub mod progress;
+pub mod queue;
+#[cfg(test)]
+mod test_server;
+
+/// How many [ProgressEvent]'s a lagging subscriber may miss.
+const PROGRESS_CAPACITY: usize = 256;
+
+/// Streams the media of a [VideoFormat] to disk.
+///
+/// Cloning is cheap, the clones share the same HTTP client and report
+/// progress to the same subscribers.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    client: Client,
+    progress: broadcast::Sender<ProgressEvent>,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self {
+            client: Client::default(),
+            progress: broadcast::channel(PROGRESS_CAPACITY).0,
+        }
+    }
+}
+
+impl Downloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive the [ProgressEvent]'s of every download started from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.progress.subscribe()
+    }
+
+    /// Download the media of `format` into the file at `destination`.
+    /// Returns the size of the downloaded file.
+    ///
+    /// The media is written to a [part file](part_path) that is renamed to
+    /// `destination` once complete. If a part file is already there, the
+    /// download resumes where it left off when the server supports range
+    /// requests, otherwise it starts over.
+    ///
+    /// While the download is ongoing, `video` is flagged as
+    /// [downloading](ManagedVideo::is_downloading) and its
+    /// [content size](ManagedVideo::get_content_size) is set from the response.
+    /// Progress is reported to the [subscribers](Self::subscribe).
+    ///
+    /// # Cancellation
+    /// Calling [cancel_download](ManagedVideo::cancel_download) on any clone
+    /// of `video` stops the download with [Error::Cancelled], or prevents it
+    /// from starting if it's called before.
+    /// The part file is kept so the download can be resumed later.
+    pub async fn download(
+        &self,
+        video: &mut ManagedVideo,
+        format: &VideoFormat,
+        destination: impl AsRef<Path>,
+    ) -> Result<u64> {
+        self.download_as(video, format, destination, None).await
+    }
+
+    /// [download](Self::download) on behalf of the queue entry with the
+    /// row id `queue_id`, which its [ProgressEvent]'s are tagged with.
+    pub(crate) async fn download_as(
+        &self,
+        video: &mut ManagedVideo,
+        format: &VideoFormat,
+        destination: impl AsRef<Path>,
+        queue_id: Option<i32>,
+    ) -> Result<u64> {
+        let url = format.url.as_deref().ok_or(Error::MissingUrl)?;
+
+        video.set_downloading(true);
+        // Checked once flagged, a cancellation in between is seen by write_body
+        let result = match video.is_cancelled() {
+            true => Err(Error::Cancelled),
+            false => {
+                self.stream_to_file(video, url, destination.as_ref(), queue_id)
+                    .await
+            }
+        };
+        video.set_downloading(false);
+
+        result
+    }
+
+    async fn stream_to_file(
+        &self,
+        video: &mut ManagedVideo,
+        url: &str,
+        destination: &Path,
+        queue_id: Option<i32>,
+    ) -> Result<u64> {
+        let part = part_path(destination);
+        let offset = partial_len(destination).await;
+
+        let mut response = self.request(url, offset).await?;
+        if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            // The part file doesn't match the media anymore
+            response = self.request(url, 0).await?;
+        }
+        if !response.status().is_success() {
+            return Err(Error::Status(response.status()));
+        }
+
+        let resumed = offset > 0
+            && response.status() == StatusCode::PARTIAL_CONTENT
+            && content_range(&response).is_some_and(|(start, _)| start == offset);
+        let (mut file, offset) = if resumed {
+            (OpenOptions::new().append(true).open(&part).await?, offset)
+        } else {
+            (File::create(&part).await?, 0)
+        };
+        video.set_content_size(match content_range(&response) {
+            Some((_, Some(total))) if resumed => Some(total),
+            _ => response.content_length().map(|len| len + offset),
+        });
+
+        let result = self
+            .write_body(video, queue_id, response, &mut file, offset)
+            .await;
+        // Keep what was received so the download can be resumed
+        file.flush().await?;
+        drop(file);
+
+        let len = result?;
+        tokio::fs::rename(&part, destination).await?;
+
+        Ok(len)
+    }
+
+    async fn request(&self, url: &str, offset: u64) -> Result<reqwest::Response> {
+        let mut request = self.client.get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+
+        Ok(request.send().await?)
+    }
+
+    async fn write_body(
+        &self,
+        video: &ManagedVideo,
+        queue_id: Option<i32>,
+        mut response: reqwest::Response,
+        file: &mut File,
+        offset: u64,
+    ) -> Result<u64> {
+        let mut tracker =
+            ProgressTracker::new(video.get_id(), queue_id, video.get_content_size(), offset);
+        let mut len = offset;
+        loop {
+            // Enabled before checking so that a cancellation in between isn't missed
+            let cancelled = video.cancelled();
+            tokio::pin!(cancelled);
+            cancelled.as_mut().enable();
+            if video.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            // A stalled response can be cancelled too
+            let chunk = tokio::select! {
+                chunk = response.chunk() => chunk?,
+                _ = cancelled => return Err(Error::Cancelled),
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            file.write_all(&chunk).await?;
+            len += chunk.len() as u64;
+
+            if let Some(event) = tracker.advance(chunk.len() as u64) {
+                // Nobody listening is fine
+                let _ = self.progress.send(event);
+            }
+        }
+        let _ = self.progress.send(tracker.finish());
+
+        Ok(len)
+    }
+}
+
+/// Path of the file a download to `destination` is written to until it
+/// is complete.
+pub fn part_path(destination: impl AsRef<Path>) -> PathBuf {
+    let mut part = destination.as_ref().as_os_str().to_owned();
+    part.push(".part");
+    part.into()
+}
+
+/// How many bytes of a download to `destination` are already in its
+/// [part file](part_path).
+pub async fn partial_len(destination: impl AsRef<Path>) -> u64 {
+    tokio::fs::metadata(part_path(destination))
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
+/// Name of the file a video is saved as, `<title> [<video id>].<container>`.
+/// Characters that aren't allowed in file names are replaced.
+pub fn file_name(video_info: &VideoInfo, format: &VideoFormat) -> String {
+    let title: String = video_info
+        .title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    format!(
+        "{} [{}].{}",
+        title.trim(),
+        video_info.video_id,
+        format.container
+    )
+}
+
+/// The start and, if known, the total size of a `Content-Range` header.
+fn content_range(response: &reqwest::Response) -> Option<(u64, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+
+    Some((start.parse().ok()?, total.parse().ok()))
+}
+
+/// Registry of the [ManagedVideo]'s currently being downloaded, shared by
+/// every screen of the app.
+///
+/// Cloning is cheap, the clones share the same registry.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadManager {
+    downloader: Downloader,
+    active: Arc<Mutex<HashMap<i32, ManagedVideo>>>,
+}
+
+impl DownloadManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Download `video` in the chosen `format` to `destination`, keeping track
+    /// of it until it finishes. See [Downloader::download].
+    pub async fn download(
+        &self,
+        video: ManagedVideo,
+        format: &VideoFormat,
+        destination: impl AsRef<Path>,
+    ) -> Result<u64> {
+        self.download_as(video, format, destination, None).await
+    }
+
+    /// [download](Self::download) on behalf of the queue entry with the
+    /// row id `queue_id`, see [Downloader::download_as].
+    pub(crate) async fn download_as(
+        &self,
+        mut video: ManagedVideo,
+        format: &VideoFormat,
+        destination: impl AsRef<Path>,
+        queue_id: Option<i32>,
+    ) -> Result<u64> {
+        let id = video.get_id();
+        // The clone shares the download state, e.g. the content size once known
+        self.active.lock().unwrap().insert(id, video.clone());
+
+        let result = self
+            .downloader
+            .download_as(&mut video, format, destination, queue_id)
+            .await;
+
+        self.active.lock().unwrap().remove(&id);
+        result
+    }
+
+    /// Cancel the download of the video with the matching row `id`.
+    /// Returns whether it was being downloaded.
+    pub fn cancel(&self, id: i32) -> bool {
+        match self.active.lock().unwrap().get(&id) {
+            Some(video) => {
+                video.cancel_download();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the video with the matching row `id` is being downloaded.
+    pub fn is_downloading(&self, id: i32) -> bool {
+        self.active.lock().unwrap().contains_key(&id)
+    }
+
+    /// The videos currently being downloaded, in no particular order.
+    pub fn active(&self) -> Vec<ManagedVideo> {
+        self.active.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Receive the [ProgressEvent]'s of every download started from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.downloader.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        file_name, part_path, partial_len, progress::ProgressEvent, test_server::TestServer,
+        DownloadManager, Downloader,
+    };
+    use crate::{
+        test_util::{test_format, TempDir, VideoInfoBuilder},
+        video::ManagedVideo,
+        Error,
+    };
+
+    fn test_video() -> ManagedVideo {
+        ManagedVideo::new(1, VideoInfoBuilder::new(1).build())
+    }
+
+    fn get_fixture_bytes() -> Vec<u8> {
+        (0..256 * 1024).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn sanitized_file_name() {
+        let mut video_info = test_video().get_info().clone();
+        video_info.title = " AC/DC: Live? <Part 1|2> ".to_string();
+        let format = test_format("");
+
+        assert_eq!(
+            file_name(&video_info, &format),
+            "AC_DC_ Live_ _Part 1_2_ [id1].mp4"
+        );
+    }
+
+    #[tokio::test]
+    async fn download_to_file() {
+        let body = get_fixture_bytes();
+        let server = TestServer::serve(body.clone()).await;
+        let dir = TempDir::new("download_to_file");
+        let destination = dir.join("video.mp4");
+
+        let mut video = test_video();
+        let written = Downloader::new()
+            .download(&mut video, &test_format(server.url()), &destination)
+            .await
+            .unwrap();
+
+        assert_eq!(written, body.len() as u64);
+        assert_eq!(video.get_content_size(), Some(body.len() as u64));
+        assert!(!video.is_downloading());
+        assert_eq!(std::fs::read(&destination).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn progress_events() {
+        let body = get_fixture_bytes();
+        let server = TestServer::serve(body.clone()).await;
+        let dir = TempDir::new("progress_events");
+        let destination = dir.join("video.mp4");
+
+        let downloader = Downloader::new();
+        let mut events = downloader.subscribe();
+
+        let mut video = test_video();
+        downloader
+            .download(&mut video, &test_format(server.url()), &destination)
+            .await
+            .unwrap();
+
+        let mut last = None;
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.id, video.get_id());
+            assert_eq!(event.total, Some(body.len() as u64));
+            if let Some(ProgressEvent { downloaded, .. }) = last {
+                assert!(event.downloaded >= downloaded);
+            }
+            last = Some(event);
+        }
+        assert_eq!(
+            last.map(|event| event.downloaded),
+            Some(body.len() as u64),
+            "The last event should report the whole body as downloaded"
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_url() {
+        let mut video = test_video();
+        let mut format = test_format("");
+        format.url = None;
+
+        let dir = TempDir::new("missing_url");
+        let result = Downloader::new()
+            .download(&mut video, &format, dir.join("video.mp4"))
+            .await;
+
+        assert!(matches!(result, Err(Error::MissingUrl)));
+    }
+
+    #[tokio::test]
+    async fn unsuccessful_status() {
+        let server = TestServer::serve(get_fixture_bytes()).await;
+        let dir = TempDir::new("unsuccessful_status");
+        let destination = dir.join("video.mp4");
+
+        let mut video = test_video();
+        let url = format!("{}/missing", server.url());
+        let result = Downloader::new()
+            .download(&mut video, &test_format(url), &destination)
+            .await;
+
+        assert!(matches!(result, Err(Error::Status(status)) if status == 404));
+        assert!(!destination.exists());
+    }
+
+    #[tokio::test]
+    async fn cancel_download() {
+        let server = TestServer::serve_stalled(get_fixture_bytes()).await;
+        let dir = TempDir::new("cancel_download");
+        let destination = dir.join("video.mp4");
+
+        let video = test_video();
+        let format = test_format(server.url());
+        let task = tokio::spawn({
+            let mut video = video.clone();
+            let destination = destination.clone();
+            async move {
+                Downloader::new()
+                    .download(&mut video, &format, &destination)
+                    .await
+            }
+        });
+
+        // The rest of the body is never sent, the download stops anyway
+        server.wait_for_request().await;
+        video.cancel_download();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("a stalled download should be cancelled")
+            .unwrap();
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(!video.is_downloading());
+        assert!(!destination.exists());
+        assert!(
+            part_path(&destination).exists(),
+            "The part file should be kept after cancelling"
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_before_start() {
+        let server = TestServer::serve(get_fixture_bytes()).await;
+        let dir = TempDir::new("cancel_before_start");
+        let destination = dir.join("video.mp4");
+
+        let mut video = test_video();
+        video.cancel_download();
+        let result = Downloader::new()
+            .download(&mut video, &test_format(server.url()), &destination)
+            .await;
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(!video.is_downloading());
+        assert!(server.requested_ranges().is_empty(), "Nothing is requested");
+        assert!(!part_path(&destination).exists());
+    }
+
+    #[tokio::test]
+    async fn manager_tracks_active() {
+        let body = get_fixture_bytes();
+        let server = TestServer::serve_stalled(body.clone()).await;
+        let dir = TempDir::new("manager_tracks_active");
+        let destination = dir.join("video.mp4");
+
+        let manager = DownloadManager::new();
+        let task = tokio::spawn({
+            let manager = manager.clone();
+            let format = test_format(server.url());
+            let destination = destination.clone();
+            async move { manager.download(test_video(), &format, &destination).await }
+        });
+
+        server.wait_for_request().await;
+        // The content size is set once the response has been received
+        let active = loop {
+            let active = manager.active();
+            if active
+                .iter()
+                .any(|video| video.get_content_size().is_some())
+            {
+                break active;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].get_content_size(), Some(body.len() as u64));
+        assert!(manager.is_downloading(1));
+
+        assert!(manager.cancel(1));
+        let result = task.await.unwrap();
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(!manager.is_downloading(1));
+        assert!(!manager.cancel(1));
+    }
+
+    #[tokio::test]
+    async fn resume_with_range() {
+        let body = get_fixture_bytes();
+        let server = TestServer::serve(body.clone()).await;
+        let dir = TempDir::new("resume_with_range");
+        let destination = dir.join("video.mp4");
+
+        let offset = body.len() / 3;
+        std::fs::write(part_path(&destination), &body[..offset]).unwrap();
+
+        let mut video = test_video();
+        let len = Downloader::new()
+            .download(&mut video, &test_format(server.url()), &destination)
+            .await
+            .unwrap();
+
+        assert_eq!(server.requested_ranges(), vec![Some(offset as u64)]);
+        assert_eq!(len, body.len() as u64);
+        assert_eq!(video.get_content_size(), Some(body.len() as u64));
+        assert_eq!(std::fs::read(&destination).unwrap(), body);
+        assert!(!part_path(&destination).exists());
+    }
+
+    #[tokio::test]
+    async fn restart_when_range_ignored() {
+        let body = get_fixture_bytes();
+        let server = TestServer::serve_ignoring_range(body.clone()).await;
+        let dir = TempDir::new("restart_when_range_ignored");
+        let destination = dir.join("video.mp4");
+
+        // Garbage that must not end up in the downloaded file
+        std::fs::write(part_path(&destination), vec![u8::MAX; body.len() / 3]).unwrap();
+
+        let mut video = test_video();
+        let len = Downloader::new()
+            .download(&mut video, &test_format(server.url()), &destination)
+            .await
+            .unwrap();
+
+        assert_eq!(server.requested_ranges(), vec![Some(body.len() as u64 / 3)]);
+        assert_eq!(len, body.len() as u64);
+        assert_eq!(std::fs::read(&destination).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn restart_when_range_not_satisfiable() {
+        let body = get_fixture_bytes();
+        let server = TestServer::serve(body.clone()).await;
+        let dir = TempDir::new("restart_when_range_not_satisfiable");
+        let destination = dir.join("video.mp4");
+
+        // Longer than the media, e.g. it changed since the part was written
+        std::fs::write(part_path(&destination), vec![u8::MAX; body.len() * 2]).unwrap();
+
+        let mut video = test_video();
+        Downloader::new()
+            .download(&mut video, &test_format(server.url()), &destination)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server.requested_ranges(),
+            vec![Some(body.len() as u64 * 2), None]
+        );
+        assert_eq!(std::fs::read(&destination).unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn resume_after_cancel() {
+        let body = get_fixture_bytes();
+        let server = TestServer::serve_stalled(body.clone()).await;
+        let dir = TempDir::new("resume_after_cancel");
+        let destination = dir.join("video.mp4");
+
+        let downloader = Downloader::new();
+        let video = test_video();
+        let format = test_format(server.url());
+        let task = tokio::spawn({
+            let downloader = downloader.clone();
+            let mut video = video.clone();
+            let format = format.clone();
+            let destination = destination.clone();
+            async move { downloader.download(&mut video, &format, &destination).await }
+        });
+
+        server.wait_for_request().await;
+        video.cancel_download();
+        server.release();
+        assert!(matches!(task.await.unwrap(), Err(Error::Cancelled)));
+        let offset = partial_len(&destination).await;
+
+        let task = tokio::spawn({
+            let mut video = video.with_fresh_download();
+            let destination = destination.clone();
+            async move { downloader.download(&mut video, &format, &destination).await }
+        });
+        server.release();
+
+        assert_eq!(task.await.unwrap().unwrap(), body.len() as u64);
+        assert_eq!(
+            server.requested_ranges().last(),
+            Some(&Some(offset).filter(|offset| *offset > 0))
+        );
+        assert_eq!(std::fs::read(&destination).unwrap(), body);
+    }
+}
@@ -0,0 +1,155 @@
+//! Progress reports of the ongoing downloads.
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// How often, at most, progress is reported for a download.
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A snapshot of the progress of a download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// Row id of the video being downloaded
+    pub id: i32,
+    /// Row id of the queue entry being downloaded, if it was started by the
+    /// [DownloadQueue](super::queue::DownloadQueue)
+    pub queue_id: Option<i32>,
+    /// Bytes downloaded so far
+    pub downloaded: u64,
+    /// Size of the media, see [ManagedVideo::get_content_size](crate::video::ManagedVideo::get_content_size)
+    pub total: Option<u64>,
+    /// Download speed since the previous report
+    pub bytes_per_sec: u64,
+}
+
+impl ProgressEvent {
+    /// Estimated time left, if the total size and the speed are known.
+    pub fn eta(&self) -> Option<Duration> {
+        let left = self.total?.saturating_sub(self.downloaded);
+        if self.bytes_per_sec == 0 {
+            return (left == 0).then_some(Duration::ZERO);
+        }
+        Some(Duration::from_secs_f64(
+            left as f64 / self.bytes_per_sec as f64,
+        ))
+    }
+
+    /// Fraction of the media downloaded, between 0 and 1, if the total size
+    /// is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total? {
+            0 => Some(1.0),
+            total => Some((self.downloaded as f64 / total as f64).min(1.0)),
+        }
+    }
+}
+
+/// Counts the bytes of a download and decides when to report its progress.
+pub(crate) struct ProgressTracker {
+    id: i32,
+    queue_id: Option<i32>,
+    total: Option<u64>,
+    downloaded: u64,
+    bytes_per_sec: u64,
+    last_report: Instant,
+    downloaded_at_last_report: u64,
+}
+
+impl ProgressTracker {
+    /// `downloaded` is how many bytes were already there when a download
+    /// is resumed.
+    pub(crate) fn new(id: i32, queue_id: Option<i32>, total: Option<u64>, downloaded: u64) -> Self {
+        Self {
+            id,
+            queue_id,
+            total,
+            downloaded,
+            bytes_per_sec: 0,
+            last_report: Instant::now(),
+            downloaded_at_last_report: downloaded,
+        }
+    }
+
+    /// Count `len` more downloaded bytes. Returns a report if enough time
+    /// has passed since the previous one.
+    pub(crate) fn advance(&mut self, len: u64) -> Option<ProgressEvent> {
+        self.downloaded += len;
+
+        let elapsed = self.last_report.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+        Some(self.report(elapsed))
+    }
+
+    /// The last report of a download, sent regardless of when the previous
+    /// one was.
+    pub(crate) fn finish(&mut self) -> ProgressEvent {
+        self.report(self.last_report.elapsed())
+    }
+
+    fn report(&mut self, elapsed: Duration) -> ProgressEvent {
+        let bytes = self.downloaded - self.downloaded_at_last_report;
+        if !elapsed.is_zero() {
+            self.bytes_per_sec = (bytes as f64 / elapsed.as_secs_f64()) as u64;
+        }
+        self.last_report = Instant::now();
+        self.downloaded_at_last_report = self.downloaded;
+
+        ProgressEvent {
+            id: self.id,
+            queue_id: self.queue_id,
+            downloaded: self.downloaded,
+            total: self.total,
+            bytes_per_sec: self.bytes_per_sec,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ProgressEvent, ProgressTracker, REPORT_INTERVAL};
+
+    fn event(downloaded: u64, total: Option<u64>, bytes_per_sec: u64) -> ProgressEvent {
+        ProgressEvent {
+            id: 1,
+            queue_id: None,
+            downloaded,
+            total,
+            bytes_per_sec,
+        }
+    }
+
+    #[test]
+    fn eta() {
+        assert_eq!(event(0, Some(100), 10).eta(), Some(Duration::from_secs(10)));
+        assert_eq!(event(50, Some(100), 10).eta(), Some(Duration::from_secs(5)));
+        assert_eq!(event(100, Some(100), 0).eta(), Some(Duration::ZERO));
+        assert_eq!(event(50, Some(100), 0).eta(), None);
+        assert_eq!(event(50, None, 10).eta(), None);
+    }
+
+    #[test]
+    fn fraction() {
+        assert_eq!(event(25, Some(100), 0).fraction(), Some(0.25));
+        assert_eq!(event(0, Some(0), 0).fraction(), Some(1.0));
+        assert_eq!(event(25, None, 0).fraction(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_are_throttled() {
+        let mut tracker = ProgressTracker::new(1, None, Some(4000), 1000);
+
+        assert_eq!(tracker.advance(1000), None);
+
+        tokio::time::advance(REPORT_INTERVAL).await;
+        let report = tracker.advance(1000).unwrap();
+        assert_eq!(report.downloaded, 3000);
+        assert_eq!(report.bytes_per_sec, 8000);
+
+        assert_eq!(tracker.advance(1000), None);
+        assert_eq!(tracker.finish().downloaded, 4000);
+    }
+}
@@ -0,0 +1,603 @@
+//! A queue running a bounded number of downloads at a time.
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use sqlx::Sqlite;
+use tokio::sync::{broadcast, Semaphore};
+
+use super::{part_path, partial_len, DownloadManager};
+use crate::{
+    database::{Database, PendingDownload},
+    video::{ManagedVideo, VideoFormat},
+    Error, Result,
+};
+
+/// How many changes a lagging [subscriber](DownloadQueue::subscribe) may miss.
+const CHANGES_CAPACITY: usize = 64;
+
+/// The state of a download in the [DownloadQueue].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadState {
+    /// Waiting for a free download slot
+    Queued,
+    Downloading,
+    /// Won't be downloaded until it's [resumed](DownloadQueue::resume)
+    Paused,
+    Done,
+    /// The download failed with the contained reason
+    Failed(String),
+}
+
+/// A download in the [DownloadQueue] and its state.
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+    /// Row id of the entry in the queue, not to be confused with the row id
+    /// of the video.
+    pub id: i32,
+    pub video: ManagedVideo,
+    pub format: VideoFormat,
+    pub destination: PathBuf,
+    pub state: DownloadState,
+    /// How many bytes were downloaded when the entry was last interrupted
+    pub resume_offset: u64,
+}
+
+/// Downloads the videos pushed to it, at most `max_concurrent` at a time and
+/// in the order they were pushed.
+///
+/// Downloads that haven't finished are stored in the database so they
+/// can be [restored](Self::restore) after a restart. Finished and failed
+/// downloads only stay in memory.
+///
+/// Cloning is cheap, the clones share the same queue.
+#[derive(Clone)]
+pub struct DownloadQueue {
+    db: Database<Sqlite>,
+    manager: DownloadManager,
+    slots: Arc<Semaphore>,
+    entries: Arc<Mutex<BTreeMap<i32, QueueEntry>>>,
+    changes: broadcast::Sender<i32>,
+}
+
+impl DownloadQueue {
+    pub fn new(db: Database<Sqlite>, manager: DownloadManager, max_concurrent: usize) -> Self {
+        Self {
+            db,
+            manager,
+            slots: Arc::new(Semaphore::new(max_concurrent)),
+            entries: Arc::default(),
+            changes: broadcast::channel(CHANGES_CAPACITY).0,
+        }
+    }
+
+    /// Receive the row id of every entry that is pushed, restored, removed
+    /// or changes state from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<i32> {
+        self.changes.subscribe()
+    }
+
+    /// Load the downloads left unfinished from the database and start the
+    /// ones that weren't paused.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub async fn restore(&self) -> Result<()> {
+        for (id, pending) in self.db.fetch_pending_downloads().await? {
+            let video = self.db.fetch_one(pending.video_info_id).await?;
+            let state = if pending.paused {
+                DownloadState::Paused
+            } else {
+                DownloadState::Queued
+            };
+            self.entries.lock().unwrap().insert(
+                id,
+                QueueEntry {
+                    id,
+                    video,
+                    format: pending.format,
+                    destination: pending.destination,
+                    state: state.clone(),
+                    resume_offset: pending.resume_offset,
+                },
+            );
+            self.notify(id);
+
+            if state == DownloadState::Queued {
+                self.spawn_download(id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queue the download of `video` in the chosen `format` to `destination`.
+    /// Returns the row id of the entry in the queue.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub async fn push(
+        &self,
+        video: ManagedVideo,
+        format: VideoFormat,
+        destination: impl Into<PathBuf>,
+    ) -> Result<i32> {
+        let destination = destination.into();
+        self.db
+            .set_chosen_format_id(video.get_id(), &format.format_id)
+            .await?;
+        let id = self
+            .db
+            .insert_pending_download(&PendingDownload {
+                video_info_id: video.get_id(),
+                format: format.clone(),
+                destination: destination.clone(),
+                paused: false,
+                resume_offset: 0,
+            })
+            .await?;
+
+        self.entries.lock().unwrap().insert(
+            id,
+            QueueEntry {
+                id,
+                video,
+                format,
+                destination,
+                state: DownloadState::Queued,
+                resume_offset: 0,
+            },
+        );
+        self.notify(id);
+        self.spawn_download(id);
+
+        Ok(id)
+    }
+
+    /// Pause the entry with the matching `id` if it is queued or downloading.
+    /// An ongoing download is cancelled and picks up where it left off once
+    /// resumed.
+    /// Returns whether the entry was paused.
+    pub async fn pause(&self, id: i32) -> Result<bool> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(&id) else {
+                return Ok(false);
+            };
+            match entry.state {
+                DownloadState::Queued => {}
+                DownloadState::Downloading => entry.video.cancel_download(),
+                _ => return Ok(false),
+            }
+            entry.state = DownloadState::Paused;
+        }
+        self.notify(id);
+        self.db.set_pending_download_paused(id, true).await?;
+
+        Ok(true)
+    }
+
+    /// Queue again the paused or failed entry with the matching `id`.
+    /// Returns whether the entry was resumed.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub async fn resume(&self, id: i32) -> Result<bool> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(&id) else {
+                return Ok(false);
+            };
+            if !matches!(
+                entry.state,
+                DownloadState::Paused | DownloadState::Failed(_)
+            ) {
+                return Ok(false);
+            }
+            // A cancelled download may still be winding down, it must not
+            // share the downloading flag with the new one.
+            entry.video = entry.video.with_fresh_download();
+            entry.state = DownloadState::Queued;
+        }
+        self.notify(id);
+        self.db.set_pending_download_paused(id, false).await?;
+        self.spawn_download(id);
+
+        Ok(true)
+    }
+
+    /// Remove the entry with the matching `id` from the queue, cancelling
+    /// it if it is being downloaded. Returns whether there was such an entry.
+    ///
+    /// What was downloaded of an unfinished entry is deleted.
+    pub async fn remove(&self, id: i32) -> Result<bool> {
+        let Some(entry) = self.entries.lock().unwrap().remove(&id) else {
+            return Ok(false);
+        };
+        self.notify(id);
+        if entry.state == DownloadState::Downloading {
+            // The download removes its own part file once it stops
+            entry.video.cancel_download();
+        } else {
+            let _ = tokio::fs::remove_file(part_path(&entry.destination)).await;
+        }
+        self.db.delete_pending_download(id).await?;
+
+        Ok(true)
+    }
+
+    /// The state of the entry with the matching `id`.
+    pub fn state(&self, id: i32) -> Option<DownloadState> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|entry| entry.state.clone())
+    }
+
+    /// Every entry of the queue, in the order they were pushed.
+    pub fn entries(&self) -> Vec<QueueEntry> {
+        self.entries.lock().unwrap().values().cloned().collect()
+    }
+
+    fn notify(&self, id: i32) {
+        // Nobody listening is fine
+        let _ = self.changes.send(id);
+    }
+
+    fn spawn_download(&self, id: i32) {
+        let queue = self.clone();
+        tokio::spawn(async move { queue.download(id).await });
+    }
+
+    async fn download(&self, id: i32) {
+        let Ok(_slot) = self.slots.acquire().await else {
+            return;
+        };
+
+        // The entry may have been paused or removed while waiting for a slot
+        let (video, format, destination) = {
+            let mut entries = self.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(&id) else {
+                return;
+            };
+            if entry.state != DownloadState::Queued {
+                return;
+            }
+            entry.state = DownloadState::Downloading;
+            (
+                entry.video.clone(),
+                entry.format.clone(),
+                entry.destination.clone(),
+            )
+        };
+        self.notify(id);
+
+        let video_id = video.get_id();
+        let result = self
+            .manager
+            .download_as(video, &format, &destination, Some(id))
+            .await;
+        let resume_offset = partial_len(&destination).await;
+        let recorded = match &result {
+            Ok(_) => match tokio::fs::metadata(&destination).await {
+                Ok(metadata) => {
+                    self.db
+                        .mark_downloaded(video_id, &destination, metadata.len())
+                        .await
+                }
+                Err(e) => Err(Error::Io(e)),
+            },
+            Err(Error::Cancelled) => Ok(0),
+            Err(_) => self.db.mark_failed(video_id).await,
+        };
+        if let Err(e) = recorded {
+            tracing::error!("failed to record the download of video {video_id}: {e}");
+        }
+
+        let done = match self.entries.lock().unwrap().get_mut(&id) {
+            Some(entry) => {
+                entry.resume_offset = resume_offset;
+                entry.state = match (result, &entry.state) {
+                    // The file is complete even if the entry was paused once
+                    // the last chunk was received
+                    (Ok(_), _) => DownloadState::Done,
+                    // Paused by the user while downloading
+                    (Err(_), DownloadState::Paused) => DownloadState::Paused,
+                    (Err(Error::Cancelled), _) => DownloadState::Paused,
+                    (Err(e), _) => DownloadState::Failed(e.to_string()),
+                };
+                Some(entry.state == DownloadState::Done)
+            }
+            None => None,
+        };
+        self.notify(id);
+        let Some(done) = done else {
+            // Removed while downloading, the part file is of no use
+            let _ = tokio::fs::remove_file(part_path(&destination)).await;
+            return;
+        };
+
+        let result = if done {
+            self.db.delete_pending_download(id).await
+        } else {
+            // Interrupted downloads resume from where they left off, even
+            // after a restart
+            match self.db.set_pending_download_paused(id, true).await {
+                Ok(_) => self.db.set_pending_download_offset(id, resume_offset).await,
+                Err(e) => Err(e),
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("failed to update download {id} of the queue: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::SqlitePool;
+
+    use super::{DownloadQueue, DownloadState};
+    use crate::{
+        database::Database,
+        download::{partial_len, test_server::TestServer, DownloadManager},
+        test_util::{test_format, TempDir, VideoInfoBuilder},
+        video::DownloadStatus,
+    };
+
+    async fn wait_for_state(queue: &DownloadQueue, id: i32, state: DownloadState) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while queue.state(id) != Some(state.clone()) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("entry {id} never became {state:?}"));
+    }
+
+    #[sqlx::test]
+    async fn bounded_concurrency(pool: SqlitePool) {
+        let db = Database { pool };
+        let queue = DownloadQueue::new(db.clone(), DownloadManager::new(), 1);
+
+        let first_server = TestServer::serve_stalled(vec![1; 1024]).await;
+        let second_server = TestServer::serve(vec![2; 1024]).await;
+        let dir = TempDir::new("queue-bounded_concurrency");
+        let destinations = [dir.join("first.mp4"), dir.join("second.mp4")];
+
+        let video_id = db
+            .insert_video_info(&VideoInfoBuilder::new(1).build())
+            .await
+            .unwrap();
+        let video = db.fetch_one(video_id).await.unwrap();
+        let first = queue
+            .push(
+                video.clone(),
+                test_format(first_server.url()),
+                &destinations[0],
+            )
+            .await
+            .unwrap();
+        let second = queue
+            .push(video, test_format(second_server.url()), &destinations[1])
+            .await
+            .unwrap();
+
+        // The second download waits for the first to finish
+        first_server.wait_for_request().await;
+        assert_eq!(queue.state(first), Some(DownloadState::Downloading));
+        assert_eq!(queue.state(second), Some(DownloadState::Queued));
+
+        first_server.release();
+        wait_for_state(&queue, first, DownloadState::Done).await;
+        wait_for_state(&queue, second, DownloadState::Done).await;
+
+        assert!(
+            db.fetch_pending_downloads().await.unwrap().is_empty(),
+            "Finished downloads should not be restored"
+        );
+
+        // The history has the last download of the video
+        let record = db.fetch_one(video_id).await.unwrap().get_record().clone();
+        assert_eq!(record.status, DownloadStatus::Downloaded);
+        assert_eq!(record.output_path.as_ref(), Some(&destinations[1]));
+        assert_eq!(record.file_size, Some(1024));
+        assert_eq!(record.chosen_format_id.as_deref(), Some("22"));
+        assert!(record.downloaded_at.is_some());
+    }
+
+    #[sqlx::test]
+    async fn changes_and_progress_per_entry(pool: SqlitePool) {
+        let db = Database { pool };
+        let manager = DownloadManager::new();
+        let queue = DownloadQueue::new(db.clone(), manager.clone(), 2);
+        let mut changes = queue.subscribe();
+        let mut progress = manager.subscribe();
+
+        let server = TestServer::serve(vec![1; 1024]).await;
+        let dir = TempDir::new("queue-changes_and_progress_per_entry");
+        let destinations = [dir.join("entry1.mp4"), dir.join("entry2.mp4")];
+
+        // Two entries of the same video
+        let video_id = db
+            .insert_video_info(&VideoInfoBuilder::new(1).build())
+            .await
+            .unwrap();
+        let video = db.fetch_one(video_id).await.unwrap();
+        let mut ids = Vec::new();
+        for destination in &destinations {
+            let id = queue
+                .push(video.clone(), test_format(server.url()), destination)
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        for &id in &ids {
+            wait_for_state(&queue, id, DownloadState::Done).await;
+        }
+
+        // Pushed, downloading and done
+        let mut changed = Vec::new();
+        while let Ok(id) = changes.try_recv() {
+            changed.push(id);
+        }
+        for id in &ids {
+            assert_eq!(changed.iter().filter(|&changed| changed == id).count(), 3);
+        }
+
+        let mut reported = Vec::new();
+        while let Ok(event) = progress.try_recv() {
+            assert_eq!(event.id, video_id);
+            reported.extend(event.queue_id);
+        }
+        reported.sort();
+        reported.dedup();
+        assert_eq!(reported, ids);
+    }
+
+    #[sqlx::test]
+    async fn failed_download(pool: SqlitePool) {
+        let db = Database { pool };
+        let queue = DownloadQueue::new(db.clone(), DownloadManager::new(), 1);
+
+        let server = TestServer::serve(Vec::new()).await;
+        let dir = TempDir::new("queue-failed_download");
+        let video_id = db
+            .insert_video_info(&VideoInfoBuilder::new(1).build())
+            .await
+            .unwrap();
+        let video = db.fetch_one(video_id).await.unwrap();
+        let url = format!("{}/missing", server.url());
+        let id = queue
+            .push(video, test_format(url), dir.join("video.mp4"))
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !matches!(queue.state(id), Some(DownloadState::Failed(_))) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let record = db.fetch_one(video_id).await.unwrap().get_record().clone();
+        assert_eq!(record.status, DownloadStatus::Failed);
+        assert_eq!(record.downloaded_at, None);
+    }
+
+    #[sqlx::test]
+    async fn pause_before_first_chunk(pool: SqlitePool) {
+        let db = Database { pool };
+        let manager = DownloadManager::new();
+        let queue = DownloadQueue::new(db.clone(), manager.clone(), 1);
+
+        let server = TestServer::serve_stalled(vec![1; 1024]).await;
+        let dir = TempDir::new("queue-pause_before_first_chunk");
+        let destination = dir.join("video.mp4");
+
+        let video_id = db
+            .insert_video_info(&VideoInfoBuilder::new(1).build())
+            .await
+            .unwrap();
+        let video = db.fetch_one(video_id).await.unwrap();
+        let id = queue
+            .push(video, test_format(server.url()), &destination)
+            .await
+            .unwrap();
+
+        // Paused as soon as the entry is taken, likely before the downloader
+        // has even started
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while queue.state(id) != Some(DownloadState::Downloading) {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(queue.pause(id).await.unwrap());
+        // Would let a download that missed the pause complete
+        server.release();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while manager.is_downloading(video_id) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        // Let the download task record how it ended
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(queue.state(id), Some(DownloadState::Paused));
+        assert!(!destination.exists());
+        let pendings = db.fetch_pending_downloads().await.unwrap();
+        assert!(pendings[0].1.paused);
+    }
+
+    #[sqlx::test]
+    async fn pause_resume_and_restore(pool: SqlitePool) {
+        let db = Database { pool };
+        let manager = DownloadManager::new();
+        let queue = DownloadQueue::new(db.clone(), manager.clone(), 1);
+
+        let server = TestServer::serve_stalled(vec![1; 1024]).await;
+        let dir = TempDir::new("queue-restore");
+        let destination = dir.join("video.mp4");
+
+        let video_id = db
+            .insert_video_info(&VideoInfoBuilder::new(1).build())
+            .await
+            .unwrap();
+        let video = db.fetch_one(video_id).await.unwrap();
+        let id = queue
+            .push(video, test_format(server.url()), &destination)
+            .await
+            .unwrap();
+
+        server.wait_for_request().await;
+        assert!(queue.pause(id).await.unwrap());
+        server.release();
+        assert_eq!(queue.state(id), Some(DownloadState::Paused));
+
+        // A new queue, as after a restart, picks up the paused download
+        // Wait for the interrupted download to record its offset
+        let resume_offset = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let offset = queue.entries()[0].resume_offset;
+                let pendings = db.fetch_pending_downloads().await.unwrap();
+                if !manager.is_downloading(video_id)
+                    && offset == partial_len(&destination).await
+                    && pendings[0].1.resume_offset == offset
+                {
+                    break offset;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let restored = DownloadQueue::new(db.clone(), DownloadManager::new(), 1);
+        restored.restore().await.unwrap();
+        let entries = restored.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, id);
+        assert_eq!(entries[0].video.get_id(), video_id);
+        assert_eq!(entries[0].state, DownloadState::Paused);
+        assert_eq!(entries[0].resume_offset, resume_offset);
+
+        assert!(restored.resume(id).await.unwrap());
+        let entries = restored.entries();
+        assert_eq!(
+            entries[0].video.get_record().chosen_format_id.as_deref(),
+            Some("22"),
+            "The record is kept when resuming"
+        );
+        assert!(!entries[0].video.is_cancelled());
+        server.release();
+        wait_for_state(&restored, id, DownloadState::Done).await;
+    }
+}
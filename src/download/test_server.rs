@@ -0,0 +1,195 @@
+//! A minimal HTTP server standing in for the media hosts during tests.
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Notify,
+};
+
+const MEDIA_PATH: &str = "/video";
+
+pub struct TestServer {
+    addr: SocketAddr,
+    requested: Arc<Notify>,
+    release: Arc<Notify>,
+    ranges: Arc<Mutex<Vec<Option<u64>>>>,
+}
+
+#[derive(Clone, Copy)]
+struct Behavior {
+    stalled: bool,
+    honor_range: bool,
+}
+
+impl TestServer {
+    /// Serve `body` at [url](Self::url) and respond with 404 to any other path.
+    /// Range requests are honored.
+    pub async fn serve(body: Vec<u8>) -> Self {
+        Self::start(
+            body,
+            Behavior {
+                stalled: false,
+                honor_range: true,
+            },
+        )
+        .await
+    }
+
+    /// Like [serve](Self::serve) but the whole body is always sent.
+    pub async fn serve_ignoring_range(body: Vec<u8>) -> Self {
+        Self::start(
+            body,
+            Behavior {
+                stalled: false,
+                honor_range: false,
+            },
+        )
+        .await
+    }
+
+    /// Like [serve](Self::serve) but only the first half of the response body
+    /// is sent until [release](Self::release) is called.
+    pub async fn serve_stalled(body: Vec<u8>) -> Self {
+        Self::start(
+            body,
+            Behavior {
+                stalled: true,
+                honor_range: true,
+            },
+        )
+        .await
+    }
+
+    async fn start(body: Vec<u8>, behavior: Behavior) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Self {
+            addr: listener.local_addr().unwrap(),
+            requested: Arc::default(),
+            release: Arc::default(),
+            ranges: Arc::default(),
+        };
+
+        let body: Arc<[u8]> = body.into();
+        let requested = server.requested.clone();
+        let release = server.release.clone();
+        let ranges = server.ranges.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let body = body.clone();
+                let requested = requested.clone();
+                let release = release.clone();
+                let ranges = ranges.clone();
+                tokio::spawn(async move {
+                    let _ = respond(stream, &body, behavior, &requested, &release, &ranges).await;
+                });
+            }
+        });
+
+        server
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}{MEDIA_PATH}", self.addr)
+    }
+
+    /// The start of the `Range` header of every request for the media
+    /// received so far, in order.
+    pub fn requested_ranges(&self) -> Vec<Option<u64>> {
+        self.ranges.lock().unwrap().clone()
+    }
+
+    /// Wait until the server has responded to a request for the media.
+    pub async fn wait_for_request(&self) {
+        self.requested.notified().await;
+    }
+
+    /// Send the rest of the body of a [stalled](Self::serve_stalled) response.
+    pub fn release(&self) {
+        self.release.notify_one();
+    }
+}
+
+async fn respond(
+    stream: TcpStream,
+    body: &[u8],
+    behavior: Behavior,
+    requested: &Notify,
+    release: &Notify,
+    ranges: &Mutex<Vec<Option<u64>>>,
+) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+
+    let mut range = None;
+    let mut line = String::new();
+    while stream.read_line(&mut line).await? > 2 {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("range") {
+                range = value
+                    .trim()
+                    .strip_prefix("bytes=")
+                    .and_then(|value| value.strip_suffix('-'))
+                    .and_then(|start| start.parse::<u64>().ok());
+            }
+        }
+        line.clear();
+    }
+
+    let stream = stream.get_mut();
+    if path != MEDIA_PATH {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(());
+    }
+    ranges.lock().unwrap().push(range);
+
+    let (head, body) = match range.filter(|_| behavior.honor_range) {
+        Some(start) if start >= body.len() as u64 => {
+            let head = format!(
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            (head, &body[..0])
+        }
+        Some(start) => {
+            let head = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len() - 1,
+                body.len(),
+                body.len() as u64 - start
+            );
+            (head, &body[start as usize..])
+        }
+        None => {
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            (head, body)
+        }
+    };
+    stream.write_all(head.as_bytes()).await?;
+
+    let (first, second) = body.split_at(body.len() / 2);
+    stream.write_all(first).await?;
+    stream.flush().await?;
+    requested.notify_one();
+
+    if behavior.stalled {
+        release.notified().await;
+    }
+    stream.write_all(second).await?;
+    stream.flush().await
+}
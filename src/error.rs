@@ -0,0 +1,112 @@
+//! The errors of the database and download layers, so that callers can tell
+//! them apart without knowing about SQL or HTTP.
+use reqwest::StatusCode;
+use sqlx::migrate::MigrateError;
+
+use crate::metadata::MetadataError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The ways using the history or downloading a video can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the video isn't in the history")]
+    NotFound,
+    #[error("the video is already in the history")]
+    Duplicate,
+    #[error("failed to upgrade the history database: {0}")]
+    Migration(#[from] MigrateError),
+    /// Any other error of the history database
+    #[error("history database error: {0}")]
+    Database(sqlx::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Malformed JSON, or JSON not shaped as expected
+    #[error("invalid data: {0}")]
+    InvalidData(serde_json::Error),
+    #[error("network error: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("the server responded with {0}")]
+    Status(StatusCode),
+    #[error("the format has no URL to download from")]
+    MissingUrl,
+    #[error(transparent)]
+    Extractor(#[from] MetadataError),
+    #[error("the download was cancelled")]
+    Cancelled,
+}
+
+/// Sorts out the errors callers may want to handle from the rest.
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => Error::NotFound,
+            sqlx::Error::Database(e) if e.is_unique_violation() => Error::Duplicate,
+            sqlx::Error::Io(e) => Error::Io(e),
+            sqlx::Error::Migrate(e) => Error::Migration(*e),
+            e => Error::Database(e),
+        }
+    }
+}
+
+/// A [serde_json::Error] while writing is an I/O error, and otherwise
+/// invalid data.
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        match e.is_io() {
+            true => Error::Io(e.into()),
+            false => Error::InvalidData(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::SqlitePool;
+
+    use super::Error;
+    use crate::{database::Database, video::VideoInfo};
+
+    #[sqlx::test]
+    async fn database_errors(pool: SqlitePool) {
+        let db = Database { pool };
+
+        let error = db.fetch_one(1).await.unwrap_err();
+        assert!(matches!(error, Error::NotFound), "{error:?}");
+        assert_eq!(error.to_string(), "the video isn't in the history");
+
+        let video_info = VideoInfo::placeholder("dQw4w9WgXcQ");
+        db.insert_video_info(&video_info).await.unwrap();
+        let error = db.insert_video_info(&video_info).await.unwrap_err();
+        assert!(matches!(error, Error::Duplicate), "{error:?}");
+        assert_eq!(error.to_string(), "the video is already in the history");
+
+        db.clone().close().await;
+        let error = db.fetch_one(1).await.unwrap_err();
+        assert!(matches!(error, Error::Database(_)), "{error:?}");
+    }
+
+    #[test]
+    fn json_errors() {
+        let error: Error = serde_json::from_str::<VideoInfo>("{").unwrap_err().into();
+        assert!(matches!(error, Error::InvalidData(_)), "{error:?}");
+        let error: Error = serde_json::from_str::<VideoInfo>("{}").unwrap_err().into();
+        assert!(matches!(error, Error::InvalidData(_)), "{error:?}");
+
+        let error: Error = serde_json::to_writer(FailingWriter, &[1])
+            .unwrap_err()
+            .into();
+        assert!(matches!(error, Error::Io(_)), "{error:?}");
+    }
+
+    struct FailingWriter;
+    impl std::io::Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -1,4 +1,16 @@
 //! This crate is a desktop GUI to download YouTube videos.
 
 pub mod database;
-pub mod video;
\ No newline at end of file
+pub mod download;
+pub mod error;
+pub mod metadata;
+pub mod paths;
+pub mod selector;
+pub mod settings;
+pub mod system_trash;
+pub mod video;
+
+#[cfg(test)]
+pub(crate) mod test_util;
+
+pub use error::{Error, Result};
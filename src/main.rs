@@ -1,55 +1,78 @@
 #![allow(non_snake_case)]
 
+mod ui;
+
+use std::path::PathBuf;
+
 use dioxus::prelude::*;
 use tracing::Level;
+use ui::{AppState, History, NavBar, OpenFailure, Recovery, Settings, Stats, Trash, Workspace};
+use yd_gui::database::Database;
 
 #[derive(Clone, Routable, Debug, PartialEq)]
+#[rustfmt::skip]
 enum Route {
-    #[route("/")]
-    Home {},
-    #[route("/blog/:id")]
-    Blog { id: i32 },
+    #[layout(NavBar)]
+        #[route("/")]
+        Workspace {},
+        #[route("/history")]
+        History {},
+        #[route("/trash")]
+        Trash {},
+        #[route("/stats")]
+        Stats {},
+        #[route("/settings")]
+        Settings {},
 }
 
 fn main() {
     // Init logger
     dioxus_logger::init(Level::INFO).expect("failed to init logger");
 
+    // The database is opened before the window so every screen can rely on it.
+    // The runtime must outlive the app because the pool's tasks live on it.
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start the tokio runtime");
+    let location = Database::locate(db_flag(std::env::args_os().skip(1)));
+    let state = match &location {
+        Ok(location) => runtime
+            .block_on(AppState::init(location.clone()))
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+
     let cfg = dioxus::desktop::Config::new()
         .with_custom_head(r#"<link rel="stylesheet" href="tailwind.css">"#.to_string());
-    LaunchBuilder::desktop().with_cfg(cfg).launch(App);
-}
-
-#[component]
-fn App() -> Element {
-    rsx! {
-        Router::<Route> {}
+    let launcher = LaunchBuilder::desktop().with_cfg(cfg);
+    match state {
+        Ok(state) => launcher.with_context(state).launch(App),
+        // Rather than not starting at all, offer to restore a backup
+        Err(error) => {
+            tracing::error!("failed to open the history database: {error}");
+            let failure = OpenFailure {
+                location: location.ok(),
+                error,
+            };
+            launcher.with_context(failure).launch(Recovery)
+        }
     }
 }
 
-#[component]
-fn Blog(id: i32) -> Element {
-    rsx! {
-        Link { to: Route::Home {}, "Go to counter" }
-        "Blog post {id}"
+/// The path after `--db`, or in `--db=<path>`, among the command line `args`.
+fn db_flag(mut args: impl Iterator<Item = std::ffi::OsString>) -> Option<PathBuf> {
+    while let Some(arg) = args.next() {
+        if arg == "--db" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--db=")) {
+            return Some(PathBuf::from(path));
+        }
     }
+    None
 }
 
 #[component]
-fn Home() -> Element {
-    let mut count = use_signal(|| 0);
-
+fn App() -> Element {
     rsx! {
-        Link {
-            to: Route::Blog {
-                id: count()
-            },
-            "Go to blog"
-        }
-        div {
-            h1 { "High-Five counter: {count}" }
-            button { onclick: move |_| count += 1, "Up high!" }
-            button { onclick: move |_| count -= 1, "Down low!" }
-        }
+        Router::<Route> {}
     }
 }
@@ -0,0 +1,440 @@
+//! Extracts [VideoInfo] from the JSON that `yt-dlp --dump-json` outputs.
+use std::{ffi::OsString, io::Read, path::PathBuf, process::Stdio, time::Duration};
+
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::video::{VideoFormat, VideoInfo};
+
+/// Name of the yt-dlp binary looked up in `PATH` by default.
+pub const DEFAULT_BINARY: &str = "yt-dlp";
+
+pub type Result<T, E = MetadataError> = std::result::Result<T, E>;
+
+/// The ways extracting metadata can fail.
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataError {
+    #[error("failed to run {binary}: {source}")]
+    Spawn {
+        binary: String,
+        source: std::io::Error,
+    },
+    #[error("yt-dlp failed: {0}")]
+    Extractor(String),
+    #[error("unexpected JSON from yt-dlp: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Runs yt-dlp to get the [VideoInfo] of a URL.
+#[derive(Debug, Clone)]
+pub struct Extractor {
+    binary: PathBuf,
+    /// `PATH` of yt-dlp's process, the app's own if `None`
+    search_path: Option<OsString>,
+}
+
+impl Default for Extractor {
+    fn default() -> Self {
+        Self::with_binary(DEFAULT_BINARY)
+    }
+}
+
+impl Extractor {
+    /// An extractor running the [DEFAULT_BINARY].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An extractor running the yt-dlp binary at `binary`. A bare name is
+    /// looked up in `PATH`.
+    pub fn with_binary(binary: impl Into<PathBuf>) -> Self {
+        Self {
+            binary: binary.into(),
+            search_path: None,
+        }
+    }
+
+    /// Look a bare binary name up in `search_path` rather than in the app's
+    /// `PATH`, which yt-dlp then also runs with.
+    pub fn with_search_path(mut self, search_path: impl Into<OsString>) -> Self {
+        self.search_path = Some(search_path.into());
+        self
+    }
+
+    /// Run `yt-dlp --dump-json` on `url` and map its output.
+    /// Only the video is extracted if `url` also points to a playlist.
+    pub async fn extract(&self, url: &str) -> Result<VideoInfo> {
+        let mut command = Command::new(&self.binary);
+        if let Some(search_path) = &self.search_path {
+            command.env("PATH", search_path);
+        }
+        let output = command
+            .args(["--dump-json", "--no-playlist", "--", url])
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|source| MetadataError::Spawn {
+                binary: self.binary.display().to_string(),
+                source,
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = stderr
+                .lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| output.status.to_string());
+            return Err(MetadataError::Extractor(message));
+        }
+
+        from_slice(&output.stdout)
+    }
+}
+
+/// Map the JSON of a single video output by `yt-dlp --dump-json`.
+pub fn from_slice(json: &[u8]) -> Result<VideoInfo> {
+    Ok(serde_json::from_slice::<RawInfo>(json)?.into())
+}
+
+/// Like [from_slice] but reading the JSON from `reader`, e.g. a file
+/// or stdin.
+pub fn from_reader(reader: impl Read) -> Result<VideoInfo> {
+    Ok(serde_json::from_reader::<_, RawInfo>(reader)?.into())
+}
+
+/// The subset of the yt-dlp info dict that is used
+#[derive(Deserialize)]
+struct RawInfo {
+    id: String,
+    title: String,
+    uploader: Option<String>,
+    channel: Option<String>,
+    duration: Option<f64>,
+    thumbnail: Option<String>,
+    #[serde(default)]
+    formats: Vec<RawFormat>,
+}
+
+#[derive(Deserialize)]
+struct RawFormat {
+    format_id: String,
+    ext: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: Option<f64>,
+    vcodec: Option<String>,
+    acodec: Option<String>,
+    /// Total bitrate in kbps
+    tbr: Option<f64>,
+    /// Video bitrate in kbps
+    vbr: Option<f64>,
+    /// Audio bitrate in kbps
+    abr: Option<f64>,
+    filesize: Option<u64>,
+    filesize_approx: Option<u64>,
+    dynamic_range: Option<String>,
+    url: Option<String>,
+}
+
+impl RawFormat {
+    /// Storyboards and the like are neither
+    fn is_media(&self) -> bool {
+        self.has_video() || self.is_audio_only()
+    }
+
+    fn has_video(&self) -> bool {
+        has_codec(&self.vcodec) && self.width.is_some() && self.height.is_some()
+    }
+
+    fn is_audio_only(&self) -> bool {
+        !has_codec(&self.vcodec) && has_codec(&self.acodec)
+    }
+}
+
+/// yt-dlp uses `"none"` for a stream that is absent
+fn has_codec(codec: &Option<String>) -> bool {
+    codec.as_deref().is_some_and(|codec| codec != "none")
+}
+
+impl From<RawFormat> for VideoFormat {
+    fn from(raw: RawFormat) -> Self {
+        let is_audio_only = raw.is_audio_only();
+        let bitrate = raw.tbr.or(match (raw.vbr, raw.abr) {
+            (None, None) => None,
+            (vbr, abr) => Some(vbr.unwrap_or_default() + abr.unwrap_or_default()),
+        });
+
+        Self {
+            format_id: raw.format_id,
+            container: raw.ext,
+            width: raw.width.unwrap_or_default(),
+            height: raw.height.unwrap_or_default(),
+            fps: raw.fps.unwrap_or_default() as f32,
+            vcodec: raw.vcodec.filter(|_| !is_audio_only),
+            acodec: raw.acodec.filter(|codec| codec != "none"),
+            bitrate_kbps: bitrate.map(|kbps| kbps.round() as u32),
+            filesize: raw.filesize.or(raw.filesize_approx),
+            hdr: raw
+                .dynamic_range
+                .is_some_and(|range| !range.eq_ignore_ascii_case("SDR")),
+            is_audio_only,
+            url: raw.url,
+        }
+    }
+}
+
+impl From<RawInfo> for VideoInfo {
+    fn from(raw: RawInfo) -> Self {
+        let audio_available = raw.formats.iter().any(RawFormat::is_audio_only);
+        let video_formats = raw
+            .formats
+            .into_iter()
+            .filter(RawFormat::is_media)
+            .map(VideoFormat::from)
+            .collect();
+
+        Self {
+            video_id: raw.id,
+            title: raw.title,
+            author: raw.uploader.or(raw.channel).unwrap_or_default(),
+            // Live streams have no duration
+            duration: Duration::from_secs(raw.duration.unwrap_or_default().round() as u64),
+            thumbnail: raw.thumbnail,
+            video_formats,
+            audio_available,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::{from_reader, from_slice, Extractor, MetadataError};
+    #[cfg(unix)]
+    use crate::test_util::TempDir;
+    use crate::video::{VideoFormat, VideoInfo};
+
+    fn fixture_path(name: &str) -> PathBuf {
+        [
+            env!("CARGO_MANIFEST_DIR"),
+            "tests",
+            "fixtures",
+            "yt-dlp",
+            name,
+        ]
+        .iter()
+        .collect()
+    }
+
+    fn read_fixture(name: &str) -> Vec<u8> {
+        std::fs::read(fixture_path(name)).unwrap()
+    }
+
+    fn format(itag: u32, container: &str) -> VideoFormat {
+        VideoFormat {
+            format_id: itag.to_string(),
+            container: container.to_string(),
+            url: Some(format!(
+                "https://rr3---sn-example.googlevideo.com/videoplayback?itag={itag}"
+            )),
+            ..Default::default()
+        }
+    }
+
+    fn audio_format(
+        itag: u32,
+        container: &str,
+        acodec: &str,
+        bitrate_kbps: u32,
+        filesize: u64,
+    ) -> VideoFormat {
+        VideoFormat {
+            acodec: Some(acodec.to_string()),
+            bitrate_kbps: Some(bitrate_kbps),
+            filesize: Some(filesize),
+            is_audio_only: true,
+            ..format(itag, container)
+        }
+    }
+
+    fn video_format(
+        itag: u32,
+        container: &str,
+        (width, height, fps): (u32, u32, f32),
+        vcodec: &str,
+        bitrate_kbps: u32,
+        filesize: u64,
+    ) -> VideoFormat {
+        VideoFormat {
+            width,
+            height,
+            fps,
+            vcodec: Some(vcodec.to_string()),
+            bitrate_kbps: Some(bitrate_kbps),
+            filesize: Some(filesize),
+            ..format(itag, container)
+        }
+    }
+
+    fn expected_video() -> VideoInfo {
+        VideoInfo {
+            video_id: "dQw4w9WgXcQ".to_string(),
+            title: "Rick Astley - Never Gonna Give You Up (Official Music Video)".to_string(),
+            author: "Rick Astley".to_string(),
+            duration: Duration::from_secs(212),
+            thumbnail: Some("https://i.ytimg.com/vi/dQw4w9WgXcQ/maxresdefault.jpg".to_string()),
+            video_formats: vec![
+                audio_format(139, "m4a", "mp4a.40.5", 49, 1_294_944),
+                audio_format(251, "webm", "opus", 136, 3_437_753),
+                video_format(160, "mp4", (256, 144, 25.0), "avc1.4d400c", 79, 2_016_356),
+                VideoFormat {
+                    acodec: Some("mp4a.40.2".to_string()),
+                    ..video_format(18, "mp4", (640, 360, 25.0), "avc1.42001E", 504, 13_375_498)
+                },
+                video_format(
+                    399,
+                    "mp4",
+                    (1920, 1080, 25.0),
+                    "av01.0.08M.08",
+                    1144,
+                    30_388_424,
+                ),
+                video_format(303, "webm", (1920, 1080, 59.94), "vp9", 2512, 66_739_212),
+                VideoFormat {
+                    hdr: true,
+                    ..video_format(
+                        337,
+                        "webm",
+                        (3840, 2160, 60.0),
+                        "vp09.02.51.10.01.09.16.09.00",
+                        26014,
+                        689_376_300,
+                    )
+                },
+            ],
+            audio_available: true,
+        }
+    }
+
+    #[test]
+    fn map_video() {
+        let info = from_slice(&read_fixture("video.json")).unwrap();
+
+        assert_eq!(info, expected_video());
+    }
+
+    #[test]
+    fn map_video_without_audio() {
+        let info = from_slice(&read_fixture("video_only.json")).unwrap();
+
+        assert_eq!(info.video_id, "jNQXAC9IVRw");
+        assert_eq!(info.author, "jawed", "Should fall back to the channel");
+        assert_eq!(info.duration, Duration::from_secs(19));
+        assert_eq!(info.thumbnail, None);
+        assert_eq!(info.video_formats.len(), 2);
+        assert_eq!(info.video_formats[1].fps, 29.97);
+        assert!(!info.audio_available);
+    }
+
+    #[test]
+    fn map_live_stream() {
+        let info = from_slice(&read_fixture("live.json")).unwrap();
+
+        assert_eq!(info.duration, Duration::ZERO);
+        assert_eq!(info.video_formats.len(), 2);
+        assert!(info.video_formats[0].is_audio_only);
+        assert_eq!(info.video_formats[0].vcodec, None);
+        assert_eq!(info.video_formats[1].filesize, None);
+        assert_eq!(info.video_formats[1].bitrate_kbps, None);
+        assert!(info.audio_available);
+    }
+
+    #[test]
+    fn map_from_reader() {
+        let file = std::fs::File::open(fixture_path("video.json")).unwrap();
+
+        assert_eq!(from_reader(file).unwrap(), expected_video());
+    }
+
+    #[test]
+    fn malformed_json() {
+        assert!(matches!(
+            from_slice(br#"{"id": "dQw4w9WgXcQ"}"#),
+            Err(MetadataError::Json(_))
+        ));
+    }
+
+    /// Write an executable script named `yt-dlp` in its own directory.
+    #[cfg(unix)]
+    fn fake_yt_dlp(name: &str, script: &str) -> TempDir {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new(name);
+
+        let binary = dir.join("yt-dlp");
+        std::fs::write(&binary, format!("#!/bin/sh\n{script}")).unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        dir
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn extract_with_binary_on_path() {
+        let fixture = fixture_path("video.json");
+        let dir = fake_yt_dlp(
+            "on_path",
+            &format!(
+                r#"[ "$1" = "--dump-json" ] || exit 2
+eval "url=\${{$#}}"
+[ "$url" = "https://www.youtube.com/watch?v=dQw4w9WgXcQ" ] || exit 3
+cat "{}""#,
+                fixture.display()
+            ),
+        );
+
+        // Keep the rest of PATH for the commands of the script
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![dir.path().to_path_buf()];
+        paths.extend(std::env::split_paths(&path));
+
+        let info = Extractor::new()
+            .with_search_path(std::env::join_paths(paths).unwrap())
+            .extract("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+            .await;
+
+        assert_eq!(info.unwrap(), expected_video());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn extractor_failure() {
+        let dir = fake_yt_dlp(
+            "failure",
+            "echo 'WARNING: something minor' >&2\necho 'ERROR: [youtube] xxxxxxxxxxx: Video unavailable' >&2\nexit 1",
+        );
+
+        let result = Extractor::with_binary(dir.join("yt-dlp"))
+            .extract("https://www.youtube.com/watch?v=xxxxxxxxxxx")
+            .await;
+
+        assert!(
+            matches!(&result, Err(MetadataError::Extractor(message))
+                if message == "ERROR: [youtube] xxxxxxxxxxx: Video unavailable"),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_binary() {
+        let result = Extractor::with_binary("/nonexistent/yt-dlp")
+            .extract("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+            .await;
+
+        assert!(matches!(result, Err(MetadataError::Spawn { .. })));
+    }
+}
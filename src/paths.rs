@@ -0,0 +1,44 @@
+//! Where the app keeps its files, following the
+//! [XDG Base Directory specification](https://specifications.freedesktop.org/basedir-spec/latest/).
+use std::path::PathBuf;
+
+/// Name of the app's directory in the data and config directories.
+const APP_DIR: &str = "yd-gui";
+
+/// `$XDG_DATA_HOME`, defaulting to `~/.local/share`, or `%APPDATA%` on Windows.
+/// `None` if there is no home directory.
+pub fn data_home() -> Option<PathBuf> {
+    base_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// `$XDG_CONFIG_HOME`, defaulting to `~/.config`, or `%APPDATA%` on Windows.
+/// `None` if there is no home directory.
+pub fn config_home() -> Option<PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// The app's directory in the [data_home], where the history is kept.
+pub fn data_dir() -> Option<PathBuf> {
+    data_home().map(|dir| dir.join(APP_DIR))
+}
+
+/// The app's directory in the [config_home], where the settings are kept.
+pub fn config_dir() -> Option<PathBuf> {
+    config_home().map(|dir| dir.join(APP_DIR))
+}
+
+/// The directory in the environment variable `var` if it's absolute,
+/// as the spec asks, or else `default` in the home directory.
+fn base_dir(var: &str, default: &str) -> Option<PathBuf> {
+    let from_var = |var| std::env::var_os(var).map(PathBuf::from);
+
+    if let Some(dir) = from_var(var).filter(|dir| dir.is_absolute()) {
+        return Some(dir);
+    }
+    if cfg!(windows) {
+        return from_var("APPDATA");
+    }
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(|home| PathBuf::from(home).join(default))
+}
@@ -0,0 +1,551 @@
+//! Picks the format to download according to the user's preferences.
+use std::{cmp::Ordering, fmt};
+
+use crate::video::VideoFormat;
+
+/// What a [FormatSelector] optimizes for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Goal {
+    /// The highest resolution, then the highest bitrate
+    #[default]
+    Best,
+    /// The smallest download
+    Smallest,
+    /// The best audio only format
+    AudioOnly,
+}
+
+/// A policy choosing among the [video_formats](crate::video::VideoInfo::video_formats)
+/// of a video.
+///
+/// Preferences break ties in the order they're listed: resolution first,
+/// then container, then frame rate, then bitrate.
+///
+/// # Example
+/// ```
+/// # use yd_gui::selector::FormatSelector;
+/// // best up to 1080p, prefer mp4, prefer 60fps, avoid AV1
+/// let selector = FormatSelector::best()
+///     .max_height(1080)
+///     .prefer_container("mp4")
+///     .prefer_high_fps()
+///     .avoid_codec("av01");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FormatSelector {
+    goal: Goal,
+    max_height: Option<u32>,
+    container: Option<String>,
+    prefer_high_fps: bool,
+    avoided_codecs: Vec<String>,
+}
+
+/// The formats picked by a [FormatSelector].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Choice<'a> {
+    /// A format holding everything to download, either a video with audio,
+    /// a video for which no audio is available or an audio only format
+    Single(&'a VideoFormat),
+    /// A video only format and the audio only format to merge it with
+    Pair {
+        video: &'a VideoFormat,
+        audio: &'a VideoFormat,
+    },
+}
+
+/// Result of [FormatSelector::select].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection<'a> {
+    pub choice: Choice<'a>,
+    /// Why this choice was made, to show to the user
+    pub explanation: String,
+}
+
+impl FormatSelector {
+    /// Select the [best](Goal::Best) format.
+    pub fn best() -> Self {
+        Self::with_goal(Goal::Best)
+    }
+
+    /// Select the [smallest](Goal::Smallest) format.
+    pub fn smallest() -> Self {
+        Self::with_goal(Goal::Smallest)
+    }
+
+    /// Select the best [audio only](Goal::AudioOnly) format.
+    pub fn audio_only() -> Self {
+        Self::with_goal(Goal::AudioOnly)
+    }
+
+    pub fn with_goal(goal: Goal) -> Self {
+        Self {
+            goal,
+            ..Self::default()
+        }
+    }
+
+    /// Skip the videos taller than `height`, unless there are no others.
+    pub fn max_height(mut self, height: u32) -> Self {
+        self.max_height = Some(height);
+        self
+    }
+
+    /// Prefer formats in `container`, e.g. `"mp4"`. The audio matching an mp4
+    /// video is m4a.
+    pub fn prefer_container(mut self, container: impl Into<String>) -> Self {
+        self.container = Some(container.into());
+        self
+    }
+
+    /// Prefer 60fps over 30fps.
+    pub fn prefer_high_fps(mut self) -> Self {
+        self.prefer_high_fps = true;
+        self
+    }
+
+    /// Skip the formats whose codec starts with `codec`, e.g. `"av01"`,
+    /// unless there are no others.
+    pub fn avoid_codec(mut self, codec: impl Into<String>) -> Self {
+        self.avoided_codecs.push(codec.into());
+        self
+    }
+
+    /// Pick among `formats` according to this policy.
+    /// Returns `None` if there is nothing that can be downloaded.
+    pub fn select<'a>(&self, formats: &'a [VideoFormat]) -> Option<Selection<'a>> {
+        let mut notes = Vec::new();
+
+        let audios: Vec<&VideoFormat> = self.without_avoided(
+            formats
+                .iter()
+                .filter(|format| format.is_audio_only)
+                .collect(),
+            &mut notes,
+        );
+        if self.goal == Goal::AudioOnly {
+            let audio = self.pick_audio(&audios, self.container.as_deref())?;
+            return Some(self.explain(Choice::Single(audio), notes));
+        }
+
+        let mut videos: Vec<&VideoFormat> = formats
+            .iter()
+            .filter(|format| !format.is_audio_only)
+            .collect();
+        if let Some(max_height) = self.max_height {
+            let fitting: Vec<_> = videos
+                .iter()
+                .copied()
+                .filter(|format| format.height <= max_height)
+                .collect();
+            if fitting.is_empty() && !videos.is_empty() {
+                notes.push(format!("nothing up to {max_height}p"));
+                let lowest = videos.iter().map(|format| format.height).min();
+                videos.retain(|format| Some(format.height) == lowest);
+            } else {
+                videos = fitting;
+            }
+        }
+        let videos = self.without_avoided(videos, &mut notes);
+
+        let choice = videos
+            .into_iter()
+            .map(|video| match video.acodec {
+                Some(_) => Choice::Single(video),
+                None => {
+                    let container = audio_container(&video.container);
+                    match self.pick_audio(&audios, Some(container)) {
+                        Some(audio) => Choice::Pair { video, audio },
+                        None => Choice::Single(video),
+                    }
+                }
+            })
+            .max_by(|a, b| self.compare(a, b))?;
+
+        if let Choice::Single(video) = choice {
+            if video.acodec.is_none() {
+                notes.push("no audio available".to_string());
+            }
+        }
+        Some(self.explain(choice, notes))
+    }
+
+    /// Remove the formats with an avoided codec, unless all of them have one.
+    fn without_avoided<'a>(
+        &self,
+        formats: Vec<&'a VideoFormat>,
+        notes: &mut Vec<String>,
+    ) -> Vec<&'a VideoFormat> {
+        let allowed: Vec<_> = formats
+            .iter()
+            .copied()
+            .filter(|format| !self.is_avoided(format))
+            .collect();
+        if allowed.is_empty() && !formats.is_empty() {
+            notes.push("only avoided codecs available".to_string());
+            return formats;
+        }
+        allowed
+    }
+
+    fn is_avoided(&self, format: &VideoFormat) -> bool {
+        [&format.vcodec, &format.acodec]
+            .into_iter()
+            .flatten()
+            .any(|codec| {
+                self.avoided_codecs.iter().any(|avoided| {
+                    codec
+                        .to_ascii_lowercase()
+                        .starts_with(&avoided.to_ascii_lowercase())
+                })
+            })
+    }
+
+    /// The best audio, or the smallest one for [Goal::Smallest], preferring
+    /// `container`.
+    fn pick_audio<'a>(
+        &self,
+        audios: &[&'a VideoFormat],
+        container: Option<&str>,
+    ) -> Option<&'a VideoFormat> {
+        audios.iter().copied().max_by(|a, b| {
+            let in_container = |format: &VideoFormat| Some(format.container.as_str()) == container;
+            let size = match self.goal {
+                Goal::Smallest => prefer_smaller(a.filesize, b.filesize)
+                    .then(prefer_smaller(a.bitrate_kbps, b.bitrate_kbps)),
+                _ => a.bitrate_kbps.cmp(&b.bitrate_kbps),
+            };
+            in_container(a).cmp(&in_container(b)).then(size)
+        })
+    }
+
+    /// Orders choices from the least to the most preferred.
+    fn compare(&self, a: &Choice, b: &Choice) -> Ordering {
+        let (video_a, video_b) = (a.video(), b.video());
+        let in_container = |format: &VideoFormat| {
+            self.container
+                .as_deref()
+                .is_some_and(|container| container == format.container)
+        };
+        let preferences =
+            in_container(video_a)
+                .cmp(&in_container(video_b))
+                .then(if self.prefer_high_fps {
+                    video_a.fps.total_cmp(&video_b.fps)
+                } else {
+                    Ordering::Equal
+                });
+
+        match self.goal {
+            Goal::Smallest => prefer_smaller(a.filesize(), b.filesize())
+                .then(prefer_smaller(a.bitrate(), b.bitrate()))
+                .then(preferences),
+            _ => video_a
+                .height
+                .cmp(&video_b.height)
+                .then(preferences)
+                .then(a.bitrate().cmp(&b.bitrate())),
+        }
+    }
+
+    fn explain<'a>(&self, choice: Choice<'a>, notes: Vec<String>) -> Selection<'a> {
+        let mut explanation = format!("{choice}: {self}");
+        for note in notes {
+            explanation.push_str("; ");
+            explanation.push_str(&note);
+        }
+
+        Selection {
+            choice,
+            explanation,
+        }
+    }
+}
+
+/// e.g. `best up to 1080p, prefer mp4, prefer high frame rates, avoid av01`
+impl fmt::Display for FormatSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.goal {
+            Goal::Best => write!(f, "best")?,
+            Goal::Smallest => write!(f, "smallest file")?,
+            Goal::AudioOnly => write!(f, "audio only")?,
+        }
+        if let (Some(max_height), false) = (self.max_height, self.goal == Goal::AudioOnly) {
+            write!(f, " up to {max_height}p")?;
+        }
+        if let Some(container) = &self.container {
+            write!(f, ", prefer {container}")?;
+        }
+        if self.prefer_high_fps && self.goal != Goal::AudioOnly {
+            write!(f, ", prefer high frame rates")?;
+        }
+        for codec in &self.avoided_codecs {
+            write!(f, ", avoid {codec}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Choice<'a> {
+    /// The video format, or the audio format of an audio only choice.
+    pub fn video(&self) -> &'a VideoFormat {
+        match *self {
+            Choice::Single(format) => format,
+            Choice::Pair { video, .. } => video,
+        }
+    }
+
+    /// The audio format to merge with the [video](Self::video), if any.
+    pub fn audio(&self) -> Option<&'a VideoFormat> {
+        match *self {
+            Choice::Single(_) => None,
+            Choice::Pair { audio, .. } => Some(audio),
+        }
+    }
+
+    /// Combined bitrate of the formats, if known.
+    fn bitrate(&self) -> Option<u32> {
+        match self.audio() {
+            Some(audio) => Some(self.video().bitrate_kbps? + audio.bitrate_kbps?),
+            None => self.video().bitrate_kbps,
+        }
+    }
+
+    /// Combined file size of the formats, if known.
+    fn filesize(&self) -> Option<u64> {
+        match self.audio() {
+            Some(audio) => Some(self.video().filesize? + audio.filesize?),
+            None => self.video().filesize,
+        }
+    }
+}
+
+/// e.g. `1920x1080 60fps mp4 (avc1.64002a) + m4a audio (mp4a.40.2)`
+impl fmt::Display for Choice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        describe(f, self.video())?;
+        if let Some(audio) = self.audio() {
+            write!(f, " + ")?;
+            describe(f, audio)?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(f: &mut fmt::Formatter<'_>, format: &VideoFormat) -> fmt::Result {
+    let codec = if format.is_audio_only {
+        write!(f, "{} audio", format.container)?;
+        &format.acodec
+    } else {
+        write!(
+            f,
+            "{}x{} {}fps {}",
+            format.width,
+            format.height,
+            format.fps.round(),
+            format.container
+        )?;
+        &format.vcodec
+    };
+    match codec {
+        Some(codec) => write!(f, " ({codec})"),
+        None => Ok(()),
+    }
+}
+
+/// Orders the smaller of `a` and `b` as the greater, unknown sizes being
+/// the least preferred.
+fn prefer_smaller<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => b.cmp(&a),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+/// The audio container that can be merged into a video in `container`
+/// without reencoding.
+fn audio_container(container: &str) -> &str {
+    match container {
+        "mp4" => "m4a",
+        container => container,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Choice, FormatSelector};
+    use crate::video::VideoFormat;
+
+    fn video(id: &str, container: &str, height: u32, fps: f32, vcodec: &str) -> VideoFormat {
+        VideoFormat {
+            format_id: id.to_string(),
+            container: container.to_string(),
+            width: height * 16 / 9,
+            height,
+            fps,
+            vcodec: Some(vcodec.to_string()),
+            bitrate_kbps: Some(height * 2),
+            filesize: Some(u64::from(height) * 10_000),
+            ..Default::default()
+        }
+    }
+
+    fn combined(id: &str, container: &str, height: u32, vcodec: &str) -> VideoFormat {
+        VideoFormat {
+            acodec: Some("mp4a.40.2".to_string()),
+            ..video(id, container, height, 30.0, vcodec)
+        }
+    }
+
+    fn audio(id: &str, container: &str, acodec: &str, bitrate_kbps: u32) -> VideoFormat {
+        VideoFormat {
+            format_id: id.to_string(),
+            container: container.to_string(),
+            acodec: Some(acodec.to_string()),
+            bitrate_kbps: Some(bitrate_kbps),
+            filesize: Some(u64::from(bitrate_kbps) * 1_000),
+            is_audio_only: true,
+            ..Default::default()
+        }
+    }
+
+    /// Something like what YouTube offers
+    fn formats() -> Vec<VideoFormat> {
+        vec![
+            audio("139", "m4a", "mp4a.40.5", 48),
+            audio("140", "m4a", "mp4a.40.2", 128),
+            audio("251", "webm", "opus", 160),
+            combined("18", "mp4", 360, "avc1.42001E"),
+            video("160", "mp4", 144, 30.0, "avc1.4d400c"),
+            video("137", "mp4", 1080, 30.0, "avc1.640028"),
+            video("299", "mp4", 1080, 60.0, "avc1.64002a"),
+            video("399", "mp4", 1080, 60.0, "av01.0.09M.08"),
+            video("303", "webm", 1080, 60.0, "vp9"),
+            video("308", "webm", 1440, 60.0, "vp9"),
+            video("315", "webm", 2160, 60.0, "vp9"),
+        ]
+    }
+
+    fn ids(choice: Choice<'_>) -> (&str, Option<&str>) {
+        (
+            &choice.video().format_id,
+            choice.audio().map(|audio| audio.format_id.as_str()),
+        )
+    }
+
+    #[test]
+    fn best() {
+        let formats = formats();
+        let selection = FormatSelector::best().select(&formats).unwrap();
+
+        assert_eq!(ids(selection.choice), ("315", Some("251")));
+        assert_eq!(
+            selection.explanation,
+            "3840x2160 60fps webm (vp9) + webm audio (opus): best"
+        );
+    }
+
+    #[test]
+    fn best_with_preferences() {
+        let formats = formats();
+        let selector = FormatSelector::best()
+            .max_height(1080)
+            .prefer_container("mp4")
+            .prefer_high_fps()
+            .avoid_codec("av01");
+        let selection = selector.select(&formats).unwrap();
+
+        assert_eq!(ids(selection.choice), ("299", Some("140")));
+        assert_eq!(
+            selection.explanation,
+            "1920x1080 60fps mp4 (avc1.64002a) + m4a audio (mp4a.40.2): \
+             best up to 1080p, prefer mp4, prefer high frame rates, avoid av01"
+        );
+    }
+
+    #[test]
+    fn frame_rate_ignored_unless_preferred() {
+        let formats = vec![
+            video("299", "mp4", 1080, 60.0, "avc1.64002a"),
+            VideoFormat {
+                bitrate_kbps: Some(5000),
+                ..video("137", "mp4", 1080, 30.0, "avc1.640028")
+            },
+        ];
+        let selection = FormatSelector::best().select(&formats).unwrap();
+        assert_eq!(selection.choice.video().format_id, "137");
+
+        let selection = FormatSelector::best()
+            .prefer_high_fps()
+            .select(&formats)
+            .unwrap();
+        assert_eq!(selection.choice.video().format_id, "299");
+    }
+
+    #[test]
+    fn audio_only() {
+        let formats = formats();
+
+        let selection = FormatSelector::audio_only().select(&formats).unwrap();
+        assert_eq!(ids(selection.choice), ("251", None));
+        assert_eq!(selection.explanation, "webm audio (opus): audio only");
+
+        let selection = FormatSelector::audio_only()
+            .prefer_container("m4a")
+            .select(&formats)
+            .unwrap();
+        assert_eq!(ids(selection.choice), ("140", None));
+    }
+
+    #[test]
+    fn smallest() {
+        let formats = formats();
+        let selection = FormatSelector::smallest().select(&formats).unwrap();
+
+        // 144p video with the smallest audio is 1.488 MB, the 360p combined
+        // format is 3.6 MB
+        assert_eq!(ids(selection.choice), ("160", Some("139")));
+        assert!(selection.explanation.ends_with(": smallest file"));
+    }
+
+    #[test]
+    fn smallest_prefers_known_sizes() {
+        let formats = vec![
+            VideoFormat {
+                filesize: None,
+                ..video("160", "mp4", 144, 30.0, "avc1.4d400c")
+            },
+            combined("18", "mp4", 360, "avc1.42001E"),
+        ];
+        let selection = FormatSelector::smallest().select(&formats).unwrap();
+
+        assert_eq!(ids(selection.choice), ("18", None));
+    }
+
+    #[test]
+    fn fallbacks() {
+        let formats = vec![
+            video("399", "mp4", 1080, 30.0, "av01.0.08M.08"),
+            video("400", "mp4", 1440, 30.0, "av01.0.12M.08"),
+        ];
+        let selection = FormatSelector::best()
+            .max_height(720)
+            .avoid_codec("AV01")
+            .select(&formats)
+            .unwrap();
+
+        assert_eq!(ids(selection.choice), ("399", None));
+        assert_eq!(
+            selection.explanation,
+            "1920x1080 30fps mp4 (av01.0.08M.08): best up to 720p, avoid AV01; \
+             nothing up to 720p; only avoided codecs available; no audio available"
+        );
+    }
+
+    #[test]
+    fn nothing_to_select() {
+        assert_eq!(FormatSelector::best().select(&[]), None);
+
+        let formats = vec![video("160", "mp4", 144, 30.0, "avc1.4d400c")];
+        assert_eq!(FormatSelector::audio_only().select(&formats), None);
+    }
+}
@@ -0,0 +1,90 @@
+//! Settings kept in a JSON file of the [config directory](crate::paths::config_dir).
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+/// Name of the settings file in the config directory.
+const FILE_NAME: &str = "settings.json";
+
+/// What the user configured. The missing fields take their default value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Where the history database is, unless overridden by the command line
+    /// or the environment. See [locate](crate::database::Database::locate).
+    pub db_path: Option<PathBuf>,
+    /// How many connections to the history database are opened at most,
+    /// see [PoolOptions](crate::database::PoolOptions)
+    pub db_max_connections: Option<u32>,
+}
+
+impl Settings {
+    /// Where the settings are read from, `None` if there is no home directory.
+    pub fn file_path() -> Option<PathBuf> {
+        paths::config_dir().map(|dir| dir.join(FILE_NAME))
+    }
+
+    /// Read the settings from their [file](Self::file_path), the defaults if
+    /// there is none.
+    ///
+    /// # Errors
+    /// Fails if the file exists but can't be read or isn't valid JSON.
+    pub fn load() -> io::Result<Self> {
+        match Self::file_path() {
+            Some(path) => Self::load_from(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// [Load](Self::load) the settings, falling back to the defaults with a
+    /// warning when their file is broken, since every setting is optional.
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            tracing::warn!("ignoring the settings file, using the defaults: {e}");
+            Self::default()
+        })
+    }
+
+    /// Read the settings from the file at `path`, the defaults if there is none.
+    pub fn load_from(path: impl AsRef<Path>) -> io::Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use super::Settings;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn load_from() {
+        let dir = TempDir::new("settings");
+        let path = dir.join("settings.json");
+
+        assert_eq!(Settings::load_from(&path).unwrap(), Settings::default());
+
+        fs::write(&path, r#"{"db_path": "/data/history.db", "unknown": 1}"#).unwrap();
+        assert_eq!(
+            Settings::load_from(&path).unwrap().db_path,
+            Some(PathBuf::from("/data/history.db"))
+        );
+
+        fs::write(&path, "{}").unwrap();
+        assert_eq!(Settings::load_from(&path).unwrap(), Settings::default());
+
+        fs::write(&path, "db_path = '/data/history.db'").unwrap();
+        let error = Settings::load_from(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,239 @@
+//! Moves files to the trash of the desktop, from where the user can restore
+//! them with their file manager.
+//!
+//! Follows the [FreeDesktop.org Trash specification](https://specifications.freedesktop.org/trash-spec/trashspec-latest.html),
+//! so it's only supported on Linux and the BSDs.
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Move the file at `path` to the trash of the user.
+/// Returns where it was moved to.
+///
+/// # Errors
+/// Fails with [io::ErrorKind::Unsupported] on platforms without a
+/// FreeDesktop.org trash, or if the file or the trash can't be written to.
+pub fn move_to_trash(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    return move_to_trash_dir(path.as_ref(), &home_trash()?, std::time::SystemTime::now());
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = path;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "moving files to the trash isn't supported on this platform",
+        ))
+    }
+}
+
+/// The `Trash` directory of the [data_home](crate::paths::data_home).
+#[cfg(all(unix, not(target_os = "macos")))]
+fn home_trash() -> io::Result<PathBuf> {
+    match crate::paths::data_home() {
+        Some(data_home) => Ok(data_home.join("Trash")),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "no home directory")),
+    }
+}
+
+/// Move the file at `path` to the `files` directory of `trash`, describing it
+/// in a `.trashinfo` file of the `info` directory.
+///
+/// The files on another file system are copied over and then removed, instead
+/// of going to the trash of their own file system.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn move_to_trash_dir(
+    path: &Path,
+    trash: &Path,
+    deleted_at: std::time::SystemTime,
+) -> io::Result<PathBuf> {
+    use std::{
+        fs::{self, DirBuilder, OpenOptions},
+        io::Write,
+        os::unix::fs::DirBuilderExt,
+    };
+
+    let path = std::path::absolute(path)?;
+    // Fail early rather than leave a .trashinfo without its file
+    fs::symlink_metadata(&path)?;
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} can't be trashed", path.display()),
+        ));
+    };
+
+    let files = trash.join("files");
+    let info = trash.join("info");
+    for dir in [&files, &info] {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+
+    // Creating the .trashinfo first reserves the name, the spec's way to
+    // not overwrite what another program is trashing at the same time
+    let trashinfo = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&path),
+        deletion_date(deleted_at)
+    );
+    let (info_path, trashed_path) = (1..)
+        .map(|n| {
+            let name = numbered_name(Path::new(name), n);
+            let mut info_name = name.clone();
+            info_name.push(".trashinfo");
+            (info.join(info_name), files.join(name))
+        })
+        .find_map(|(info_path, trashed_path)| {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(mut file) => Some(
+                    file.write_all(trashinfo.as_bytes())
+                        .map(|_| (info_path, trashed_path)),
+                ),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+                Err(e) => Some(Err(e)),
+            }
+        })
+        .expect("there's always a free name")?;
+
+    let moved = match fs::rename(&path, &trashed_path) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(&path, &trashed_path).and_then(|_| fs::remove_file(&path))
+        }
+        moved => moved,
+    };
+    if let Err(e) = moved {
+        let _ = fs::remove_file(&trashed_path);
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+
+    Ok(trashed_path)
+}
+
+/// `name` the first time, then `name.2`, `name.3`… before the extension.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn numbered_name(name: &Path, n: u32) -> std::ffi::OsString {
+    if n == 1 {
+        return name.as_os_str().to_owned();
+    }
+
+    let mut numbered = name.file_stem().unwrap_or_default().to_owned();
+    numbered.push(format!(".{n}"));
+    if let Some(extension) = name.extension() {
+        numbered.push(".");
+        numbered.push(extension);
+    }
+    numbered
+}
+
+/// The path as a URL would have it, every byte but the unreserved
+/// characters and `/` escaped.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn percent_encode(path: &Path) -> String {
+    use std::{fmt::Write, os::unix::ffi::OsStrExt};
+
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => write!(encoded, "%{byte:02X}").expect("writing to a String can't fail"),
+        }
+    }
+    encoded
+}
+
+/// `YYYY-MM-DDThh:mm:ss`. The spec asks for the local time but it's written
+/// in UTC, file managers only use it to sort and display the trash.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn deletion_date(time: std::time::SystemTime) -> String {
+    let seconds = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // Howard Hinnant's days_from_civil, in reverse
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{deletion_date, move_to_trash_dir};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn deletion_dates() {
+        let at = |seconds| deletion_date(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+
+        assert_eq!(at(0), "1970-01-01T00:00:00");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00");
+        assert_eq!(at(1_718_454_896), "2024-06-15T12:34:56");
+    }
+
+    #[test]
+    fn move_to_trash() {
+        let dir = TempDir::new("move_to_trash");
+        let trash = dir.join("Trash");
+        let deleted_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_718_454_896);
+
+        let video = dir.join("Me at the zoo [jNQXAC9IVRw].mp4");
+        fs::write(&video, b"first").unwrap();
+        let trashed = move_to_trash_dir(&video, &trash, deleted_at).unwrap();
+        assert!(!video.exists());
+        assert_eq!(trashed, trash.join("files/Me at the zoo [jNQXAC9IVRw].mp4"));
+        assert_eq!(fs::read(&trashed).unwrap(), b"first");
+        let info = fs::read_to_string(trash.join("info/Me at the zoo [jNQXAC9IVRw].mp4.trashinfo"))
+            .unwrap();
+        assert_eq!(
+            info,
+            format!(
+                "[Trash Info]\nPath={}/Me%20at%20the%20zoo%20%5BjNQXAC9IVRw%5D.mp4\nDeletionDate=2024-06-15T12:34:56\n",
+                dir.path().display()
+            )
+        );
+
+        // A file of the same name doesn't overwrite the first one
+        fs::write(&video, b"second").unwrap();
+        let trashed = move_to_trash_dir(&video, &trash, deleted_at).unwrap();
+        assert_eq!(
+            trashed,
+            trash.join("files/Me at the zoo [jNQXAC9IVRw].2.mp4")
+        );
+        assert_eq!(fs::read(&trashed).unwrap(), b"second");
+        assert!(trash
+            .join("info/Me at the zoo [jNQXAC9IVRw].2.mp4.trashinfo")
+            .exists());
+
+        // Nothing is left behind for a missing file
+        let error = move_to_trash_dir(&video, &trash, deleted_at).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(fs::read_dir(trash.join("info")).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,222 @@
+//! Fixtures and temporary files shared by the tests of the crate.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::video::{VideoFormat, VideoInfo};
+
+/// A directory of the system temp dir for a test, removed with everything in
+/// it when dropped, including when the test panics.
+///
+/// It's named after the process too, so that test runs don't share it.
+#[derive(Debug)]
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create the empty directory of the test `name`, removing what a
+    /// previous run may have left.
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("yd-gui-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("the temp dir is writable");
+        Self(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Path of `name` in the directory.
+    pub(crate) fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Builds the [VideoInfo] of a test, from the `n`th test video: `id<n>`,
+/// titled `Video <n>` by `Author <n>`, `n` seconds long, with audio and
+/// without formats.
+///
+/// # Example
+/// ```ignore
+/// let video_info = VideoInfoBuilder::new(2)
+///     .formats(test_formats())
+///     .audio_available(false)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub(crate) struct VideoInfoBuilder(VideoInfo);
+
+impl VideoInfoBuilder {
+    pub(crate) fn new(n: u64) -> Self {
+        Self(VideoInfo {
+            video_id: format!("id{n}"),
+            title: format!("Video {n}"),
+            author: format!("Author {n}"),
+            duration: Duration::from_secs(n),
+            thumbnail: None,
+            video_formats: Vec::new(),
+            audio_available: true,
+        })
+    }
+
+    pub(crate) fn video_id(mut self, video_id: impl Into<String>) -> Self {
+        self.0.video_id = video_id.into();
+        self
+    }
+
+    pub(crate) fn title(mut self, title: impl Into<String>) -> Self {
+        self.0.title = title.into();
+        self
+    }
+
+    pub(crate) fn author(mut self, author: impl Into<String>) -> Self {
+        self.0.author = author.into();
+        self
+    }
+
+    pub(crate) fn duration_secs(mut self, secs: u64) -> Self {
+        self.0.duration = Duration::from_secs(secs);
+        self
+    }
+
+    pub(crate) fn thumbnail(mut self, thumbnail: impl Into<String>) -> Self {
+        self.0.thumbnail = Some(thumbnail.into());
+        self
+    }
+
+    pub(crate) fn formats(mut self, formats: Vec<VideoFormat>) -> Self {
+        self.0.video_formats = formats;
+        self
+    }
+
+    pub(crate) fn audio_available(mut self, audio_available: bool) -> Self {
+        self.0.audio_available = audio_available;
+        self
+    }
+
+    pub(crate) fn build(self) -> VideoInfo {
+        self.0
+    }
+}
+
+/// The formats of a typical video: `244` without audio, `22` with audio
+/// and `251` audio only.
+pub(crate) fn test_formats() -> Vec<VideoFormat> {
+    vec![
+        VideoFormat {
+            format_id: "244".to_string(),
+            container: "webm".to_string(),
+            width: 640,
+            height: 480,
+            fps: 30.0,
+            vcodec: Some("vp9".to_string()),
+            acodec: None,
+            bitrate_kbps: Some(500),
+            filesize: Some(5_000_000_000),
+            hdr: false,
+            is_audio_only: false,
+            url: None,
+        },
+        VideoFormat {
+            format_id: "22".to_string(),
+            container: "mp4".to_string(),
+            width: 1280,
+            height: 720,
+            fps: 60.0,
+            vcodec: Some("avc1.64001F".to_string()),
+            acodec: Some("mp4a.40.2".to_string()),
+            bitrate_kbps: None,
+            filesize: None,
+            hdr: true,
+            is_audio_only: false,
+            url: None,
+        },
+        VideoFormat {
+            format_id: "251".to_string(),
+            container: "webm".to_string(),
+            width: 0,
+            height: 0,
+            fps: 0.0,
+            vcodec: None,
+            acodec: Some("opus".to_string()),
+            bitrate_kbps: Some(160),
+            filesize: Some(3_437_753),
+            hdr: false,
+            is_audio_only: true,
+            url: None,
+        },
+    ]
+}
+
+/// The first 3 test videos with the [test formats](test_formats), the 2nd
+/// without audio.
+pub(crate) fn test_videos() -> Vec<VideoInfo> {
+    (1..=3)
+        .map(|n| {
+            VideoInfoBuilder::new(n)
+                .formats(test_formats())
+                .audio_available(n != 2)
+                .build()
+        })
+        .collect()
+}
+
+/// Real videos, one of them with a title that needs escaping in CSV and JSON.
+pub(crate) fn youtube_videos() -> Vec<VideoInfo> {
+    vec![
+        VideoInfoBuilder::new(212)
+            .video_id("dQw4w9WgXcQ")
+            .title("Never Gonna Give You Up")
+            .author("Rick Astley")
+            .thumbnail("https://i.ytimg.com/vi/dQw4w9WgXcQ/hq720.jpg")
+            .formats(vec![
+                VideoFormat {
+                    format_id: "18".to_string(),
+                    container: "mp4".to_string(),
+                    width: 640,
+                    height: 360,
+                    fps: 25.0,
+                    vcodec: Some("avc1.42001E".to_string()),
+                    acodec: Some("mp4a.40.2".to_string()),
+                    bitrate_kbps: Some(504),
+                    filesize: Some(13_375_498),
+                    ..Default::default()
+                },
+                VideoFormat {
+                    format_id: "251".to_string(),
+                    container: "webm".to_string(),
+                    acodec: Some("opus".to_string()),
+                    is_audio_only: true,
+                    ..Default::default()
+                },
+            ])
+            .build(),
+        VideoInfoBuilder::new(19)
+            .video_id("jNQXAC9IVRw")
+            .title("Me at the \"zoo\", again")
+            .author("jawed")
+            .audio_available(false)
+            .build(),
+    ]
+}
+
+/// A format with audio and video to download from `url`.
+pub(crate) fn test_format(url: impl Into<String>) -> VideoFormat {
+    VideoFormat {
+        format_id: "22".to_string(),
+        container: "mp4".to_string(),
+        width: 1280,
+        height: 720,
+        fps: 60.0,
+        url: Some(url.into()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,14 @@
+//! What to tell the user when using the history or downloading fails.
+use yd_gui::Error;
+
+/// A message for `e`, explaining the errors the user can do something about.
+pub fn describe_error(e: &Error) -> String {
+    match e {
+        Error::Duplicate => "This video is already in the history".to_string(),
+        Error::NotFound => "This video is no longer in the history".to_string(),
+        Error::Cancelled => "The download was cancelled".to_string(),
+        Error::Network(e) => format!("Couldn't reach the server, check your connection: {e}"),
+        Error::InvalidData(e) => format!("The file isn't valid: {e}"),
+        e => e.to_string(),
+    }
+}
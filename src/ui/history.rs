@@ -0,0 +1,524 @@
+use std::collections::{HashSet, VecDeque};
+
+use dioxus::prelude::*;
+use sqlx::Sqlite;
+use yd_gui::{
+    database::{
+        export::ExportFormat,
+        history::{Direction, HistoryQuery, Page, SortKey},
+        import::{ImportFormat, ImportReport},
+        Database,
+    },
+    metadata::Extractor,
+    video::{watch_url, DownloadRecord, DownloadStatus, ManagedVideo, VideoInfo},
+    Result,
+};
+
+use super::{
+    error::describe_error,
+    format::{format_bytes, format_duration},
+    AppState,
+};
+
+/// How many videos are loaded at a time.
+const PAGE_SIZE: u32 = 20;
+
+/// How many pages are kept around the scroll position. The others are
+/// dropped, and loaded again when scrolled back to.
+const MAX_PAGES: usize = 5;
+
+/// How close to an end of the list, in pixels, the page past it is loaded.
+const SCROLL_MARGIN: f64 = 300.0;
+
+/// `id` of the scrolled element.
+const LIST_ID: &str = "history-list";
+
+/// The orders the history can be shown in.
+const SORTS: [(&str, SortKey, Direction); 6] = [
+    ("Newest", SortKey::AddedAt, Direction::Descending),
+    ("Oldest", SortKey::AddedAt, Direction::Ascending),
+    ("Title", SortKey::Title, Direction::Ascending),
+    ("Author", SortKey::Author, Direction::Ascending),
+    ("Longest", SortKey::Duration, Direction::Descending),
+    ("Shortest", SortKey::Duration, Direction::Ascending),
+];
+
+/// The formats the history can be exported as.
+const EXPORTS: [(&str, ExportFormat); 3] = [
+    ("JSON", ExportFormat::Json),
+    ("CSV", ExportFormat::Csv),
+    ("yt-dlp batch file", ExportFormat::UrlList),
+];
+
+/// Screen listing the previously downloaded videos, most recent first,
+/// or those matching the search. Pages are loaded as the list is scrolled.
+#[component]
+pub fn History() -> Element {
+    let state = use_context::<AppState>();
+    let mut pages = use_signal(VecDeque::<Page<ManagedVideo>>::new);
+    let mut loading = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
+    let mut search = use_signal(String::new);
+    // How many of the search results come before the next page. Search
+    // results are fetched by offset, see load_next.
+    let mut search_offset = use_signal(|| 0u32);
+    let mut sort = use_signal(|| 0usize);
+    let mut with_audio = use_signal(|| false);
+    let mut export_format = use_signal(|| 0usize);
+    // Outcome of the last export or import
+    let mut notice = use_signal(|| None::<String>);
+    // The last video moved to the trash, until it's restored
+    let mut trashed = use_signal(|| None::<i32>);
+
+    let query = move || {
+        let (_, key, direction) = SORTS[sort()];
+        let query = HistoryQuery::new().sort_by(key, direction).limit(PAGE_SIZE);
+        match with_audio() {
+            true => query.audio_available(true),
+            false => query,
+        }
+    };
+
+    // Reloaded from the start whenever the search, sort or filter changes
+    let db = state.db.clone();
+    let mut first_page = use_resource(move || {
+        let db = db.clone();
+        let search = search();
+        let query = query();
+        async move {
+            if search.trim().is_empty() {
+                db.fetch_history(&query).await
+            } else {
+                db.search(&search, PAGE_SIZE, 0).await.map(search_page)
+            }
+        }
+    });
+    use_effect(move || match &*first_page.read() {
+        Some(Ok(page)) => {
+            search_offset.set(page.items.len() as u32);
+            pages.set(VecDeque::from([page.clone()]));
+        }
+        Some(Err(e)) => error.set(Some(describe_error(e))),
+        None => {}
+    });
+
+    let db = state.db.clone();
+    let mut load = move |backward: bool| {
+        if loading() {
+            return;
+        }
+        loading.set(true);
+        let db = db.clone();
+        let search = search();
+        let query = query();
+        spawn(async move {
+            let result = match backward {
+                false => load_next(&db, query, &search, pages, search_offset).await,
+                true => load_previous(&db, query, pages).await,
+            };
+            if let Err(e) = result {
+                error.set(Some(describe_error(&e)));
+            }
+            loading.set(false);
+        });
+    };
+    let load_on_scroll = load.clone();
+    let onscroll = move |_| {
+        let mut load = load_on_scroll.clone();
+        spawn(async move {
+            match scroll_margins().await {
+                Some((_, bottom)) if bottom < SCROLL_MARGIN => load(false),
+                Some((top, _)) if top < SCROLL_MARGIN => load(true),
+                _ => {}
+            }
+        });
+    };
+
+    let db = state.db.clone();
+    let delete = move |id: i32| {
+        let db = db.clone();
+        spawn(async move {
+            match db.trash(id).await {
+                Ok(_) => {
+                    let mut removed = false;
+                    for page in pages.write().iter_mut() {
+                        let len = page.items.len();
+                        page.items.retain(|video| video.get_id() != id);
+                        removed |= page.items.len() < len;
+                    }
+                    // The trashed video leaves the search results, the
+                    // following ones move up by one
+                    if removed {
+                        search_offset.with_mut(|offset| *offset = offset.saturating_sub(1));
+                    }
+                    trashed.set(Some(id));
+                }
+                Err(e) => error.set(Some(describe_error(&e))),
+            }
+        });
+    };
+
+    let db = state.db.clone();
+    let undo = move |_| {
+        let db = db.clone();
+        let Some(id) = trashed.take() else {
+            return;
+        };
+        spawn(async move {
+            match db.restore(id).await {
+                Ok(_) => first_page.restart(),
+                Err(e) => error.set(Some(describe_error(&e))),
+            }
+        });
+    };
+
+    // Written next to the downloads, overwriting the previous export
+    let db = state.db.clone();
+    let download_dir = state.download_dir.clone();
+    let export = move |_| {
+        let db = db.clone();
+        let (_, format) = EXPORTS[export_format()];
+        let path = download_dir.join(format!("yd-gui-history.{}", format.extension()));
+        spawn(async move {
+            let mut out = Vec::new();
+            let result = match db.export(format, &mut out).await {
+                Ok(count) => tokio::fs::write(&path, out)
+                    .await
+                    .map(|_| count)
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(describe_error(&e)),
+            };
+            match result {
+                Ok(count) => notice.set(Some(format!(
+                    "Exported {count} videos to {}",
+                    path.display()
+                ))),
+                Err(e) => error.set(Some(e)),
+            }
+        });
+    };
+
+    let db = state.db.clone();
+    let extractor = state.extractor.clone();
+    let import = move |event: FormEvent| {
+        let db = db.clone();
+        let extractor = extractor.clone();
+        let Some(files) = event.files() else {
+            return;
+        };
+        spawn(async move {
+            for file in files.files() {
+                let format = match file.rsplit_once('.').map(|(_, extension)| extension) {
+                    Some("json") => ImportFormat::Json,
+                    Some("csv") => ImportFormat::Csv,
+                    _ => ImportFormat::DownloadArchive,
+                };
+                let Some(bytes) = files.read_file(&file).await else {
+                    error.set(Some(format!("Failed to read {file}")));
+                    continue;
+                };
+                match db.import(format, bytes.as_slice()).await {
+                    Ok(report) => notice.set(Some(describe_import(&file, &report))),
+                    Err(e) => error.set(Some(describe_error(&e))),
+                }
+            }
+            first_page.restart();
+
+            if let Err(e) = fetch_missing_metadata(&db, &extractor).await {
+                error.set(Some(describe_error(&e)));
+            }
+            first_page.restart();
+        });
+    };
+
+    let has_more = pages
+        .read()
+        .back()
+        .is_some_and(|page| page.next_cursor.is_some() || page.has_more);
+
+    rsx! {
+        input { class: "w-full border rounded px-2",
+            r#type: "search",
+            placeholder: "Search titles and authors",
+            value: "{search}",
+            oninput: move |event| search.set(event.value()),
+        }
+        if search.read().trim().is_empty() {
+            div { class: "flex gap-4 mt-2",
+                select {
+                    onchange: move |event| sort.set(event.value().parse().unwrap_or_default()),
+                    for (i, (name, _, _)) in SORTS.iter().enumerate() {
+                        option { value: "{i}", selected: i == sort(), "{name}" }
+                    }
+                }
+                label {
+                    input {
+                        r#type: "checkbox",
+                        checked: with_audio(),
+                        onchange: move |event| with_audio.set(event.checked()),
+                    }
+                    " With audio"
+                }
+                select {
+                    onchange: move |event| export_format.set(event.value().parse().unwrap_or_default()),
+                    for (i, (name, _)) in EXPORTS.iter().enumerate() {
+                        option { value: "{i}", selected: i == export_format(), "{name}" }
+                    }
+                }
+                button { class: "border rounded px-2", onclick: export, "Export" }
+                label { class: "border rounded px-2",
+                    "Import"
+                    input { class: "hidden",
+                        r#type: "file",
+                        accept: ".json,.csv,.txt",
+                        onchange: import,
+                    }
+                }
+            }
+        }
+        if let Some(message) = notice() {
+            p { "{message}" }
+        }
+        if trashed().is_some() {
+            p { "Moved to the trash. "
+                button { class: "underline", onclick: undo, "Undo" }
+            }
+        }
+        if let Some(e) = error() {
+            p { class: "text-red-600", "{e}" }
+        }
+        div { id: LIST_ID, class: "overflow-y-auto max-h-[70vh]", onscroll,
+            for page in pages.read().iter() {
+                ul { key: "{page.items.first().map(ManagedVideo::get_id).unwrap_or_default()}",
+                    for video in page.items.iter() {
+                        HistoryRow {
+                            key: "{video.get_id()}",
+                            id: video.get_id(),
+                            title: describe_title(video.get_info()),
+                            author: video.get_info().author.clone(),
+                            duration: format_duration(video.get_info().duration),
+                            status: describe_status(video.get_record()),
+                            on_delete: delete.clone(),
+                        }
+                    }
+                }
+            }
+            // In case the loaded videos are too few to scroll
+            if has_more {
+                button { class: "border rounded px-4 mt-2",
+                    disabled: loading(),
+                    onclick: move |_| load(false),
+                    "Load more"
+                }
+            }
+        }
+    }
+}
+
+/// e.g. `Imported 12 videos from history.csv, restored 1 from the trash,
+/// skipped 2 duplicates.
+/// Line 7: invalid duration_seconds `abc`.`
+fn describe_import(file: &str, report: &ImportReport) -> String {
+    let mut description = format!("Imported {} videos from {file}", report.imported.len());
+    if !report.restored.is_empty() {
+        description.push_str(&format!(
+            ", restored {} from the trash",
+            report.restored.len()
+        ));
+    }
+    if !report.duplicates.is_empty() {
+        description.push_str(&format!(", skipped {} duplicates", report.duplicates.len()));
+    }
+    description.push('.');
+    for error in &report.errors {
+        description.push_str(&format!(" Line {}: {}.", error.line, error.message));
+    }
+    description
+}
+
+/// Fetch the metadata of the imported videos only known by their id.
+/// Those that can't be extracted stay placeholders.
+async fn fetch_missing_metadata(db: &Database<Sqlite>, extractor: &Extractor) -> Result<()> {
+    for video in db.fetch_placeholders(u32::MAX).await? {
+        let url = watch_url(&video.get_info().video_id);
+        if let Ok(video_info) = extractor.extract(&url).await {
+            db.upsert_video_info(&video_info).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The title, or the id of a video whose metadata isn't fetched yet.
+fn describe_title(video_info: &VideoInfo) -> String {
+    match video_info.is_placeholder() {
+        true => format!("{} (no metadata yet)", video_info.video_id),
+        false => video_info.title.clone(),
+    }
+}
+
+/// Search results are fetched by offset, so their pages have no cursor.
+fn search_page(items: Vec<ManagedVideo>) -> Page<ManagedVideo> {
+    Page {
+        has_more: items.len() == PAGE_SIZE as usize,
+        items,
+        next_cursor: None,
+        prev_cursor: None,
+    }
+}
+
+/// Append the page following the last one loaded, dropping the first one
+/// if there are too many.
+///
+/// Search results are fetched from `search_offset`, which is kept apart from
+/// how many are listed since a video added to the history in between shifts
+/// the results. The videos already listed are skipped so none is listed twice.
+async fn load_next(
+    db: &Database<Sqlite>,
+    query: HistoryQuery,
+    search: &str,
+    mut pages: Signal<VecDeque<Page<ManagedVideo>>>,
+    mut search_offset: Signal<u32>,
+) -> Result<()> {
+    let (cursor, has_more) = {
+        let pages = pages.read();
+        let Some(last) = pages.back() else {
+            return Ok(());
+        };
+        (last.next_cursor.clone(), last.has_more)
+    };
+
+    // Search results are never dropped so that they can be checked against
+    let droppable = cursor.is_some();
+    let page = match cursor {
+        Some(cursor) => db.fetch_history(&query.after(cursor)).await?,
+        None if has_more && !search.trim().is_empty() => {
+            let items = db.search(search, PAGE_SIZE, search_offset()).await?;
+            search_offset += items.len() as u32;
+
+            let listed: HashSet<i32> = pages
+                .read()
+                .iter()
+                .flat_map(|page| page.items.iter().map(ManagedVideo::get_id))
+                .collect();
+            let mut page = search_page(items);
+            page.items.retain(|video| !listed.contains(&video.get_id()));
+            page
+        }
+        None => return Ok(()),
+    };
+    let drop_first = droppable && pages.read().len() == MAX_PAGES;
+    let dropped_height = match drop_first {
+        true => first_page_height().await,
+        false => 0.0,
+    };
+
+    let mut pages = pages.write();
+    pages.push_back(page);
+    if drop_first {
+        pages.pop_front();
+        scroll_list_by(-dropped_height);
+    }
+
+    Ok(())
+}
+
+/// Prepend the page preceding the first one loaded, dropping the last one
+/// if there are too many.
+async fn load_previous(
+    db: &Database<Sqlite>,
+    query: HistoryQuery,
+    mut pages: Signal<VecDeque<Page<ManagedVideo>>>,
+) -> Result<()> {
+    let cursor = pages
+        .read()
+        .front()
+        .and_then(|page| page.prev_cursor.clone());
+    let Some(cursor) = cursor else {
+        return Ok(());
+    };
+
+    let page = db.fetch_history(&query.before(cursor)).await?;
+
+    let mut pages = pages.write();
+    pages.push_front(page);
+    if pages.len() > MAX_PAGES {
+        pages.pop_back();
+    }
+    scroll_past_first_page();
+
+    Ok(())
+}
+
+/// Distances in pixels between the top and bottom of the list and its
+/// visible part.
+async fn scroll_margins() -> Option<(f64, f64)> {
+    let mut js = eval(&format!(
+        r#"const list = document.getElementById("{LIST_ID}");
+        dioxus.send([list.scrollTop, list.scrollHeight - list.clientHeight - list.scrollTop]);"#
+    ));
+    serde_json::from_value(js.recv().await.ok()?).ok()
+}
+
+/// Height in pixels of the first page of the list.
+async fn first_page_height() -> f64 {
+    let mut js = eval(&format!(
+        r#"const page = document.getElementById("{LIST_ID}").firstElementChild;
+        dioxus.send(page ? page.offsetHeight : 0);"#
+    ));
+    let height = js.recv().await.ok();
+    height
+        .and_then(|height| height.as_f64())
+        .unwrap_or_default()
+}
+
+/// Scroll the list by `pixels` once rendered, to keep the same videos in
+/// view after a page above them was dropped.
+fn scroll_list_by(pixels: f64) {
+    eval(&format!(
+        r#"requestAnimationFrame(() => {{
+            document.getElementById("{LIST_ID}").scrollTop += {pixels};
+        }});"#
+    ));
+}
+
+/// Scroll the list down by the height of its first page once rendered, to
+/// keep the same videos in view after a page was added above them.
+fn scroll_past_first_page() {
+    eval(&format!(
+        r#"requestAnimationFrame(() => {{
+            const list = document.getElementById("{LIST_ID}");
+            list.scrollTop += list.firstElementChild.offsetHeight;
+        }});"#
+    ));
+}
+
+#[component]
+fn HistoryRow(
+    id: i32,
+    title: String,
+    author: String,
+    duration: String,
+    status: String,
+    on_delete: EventHandler<i32>,
+) -> Element {
+    rsx! {
+        li { class: "flex justify-between py-1",
+            div {
+                p { class: "font-semibold", "{title}" }
+                p { class: "text-sm", "{author} · {duration} · {status}" }
+            }
+            button { onclick: move |_| on_delete.call(id), "Delete" }
+        }
+    }
+}
+
+/// e.g. `Downloaded, 12.3 MiB`
+fn describe_status(record: &DownloadRecord) -> String {
+    match (record.status, record.file_size) {
+        (DownloadStatus::Added, _) => "Not downloaded".to_string(),
+        (DownloadStatus::Downloaded, Some(size)) => format!("Downloaded, {}", format_bytes(size)),
+        (DownloadStatus::Downloaded, None) => "Downloaded".to_string(),
+        (DownloadStatus::Failed, _) => "Download failed".to_string(),
+    }
+}
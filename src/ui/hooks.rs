@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use dioxus::prelude::*;
+use tokio::sync::broadcast::error::RecvError;
+use yd_gui::download::progress::ProgressEvent;
+
+use super::AppState;
+
+/// Latest [ProgressEvent] of every download of the queue, keyed by the row id
+/// of the queue entry. The signal updates as the downloads report their
+/// progress.
+pub fn use_download_progress() -> Signal<HashMap<i32, ProgressEvent>> {
+    let state = use_context::<AppState>();
+    let mut progress = use_signal(HashMap::new);
+
+    use_future(move || {
+        let mut events = state.downloads.subscribe();
+        async move {
+            loop {
+                match events.recv().await {
+                    Ok(
+                        event @ ProgressEvent {
+                            queue_id: Some(id), ..
+                        },
+                    ) => {
+                        progress.write().insert(id, event);
+                    }
+                    // Not started by the queue
+                    Ok(_) => continue,
+                    // Only the latest event matters, skip the missed ones
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    });
+
+    progress
+}
+
+/// Revision of the download queue, bumped whenever one of its entries is
+/// pushed, removed or changes state.
+pub fn use_queue_revision() -> Signal<usize> {
+    let state = use_context::<AppState>();
+    let mut revision = use_signal(|| 0);
+
+    use_future(move || {
+        let mut changes = state.queue.subscribe();
+        async move {
+            // Missed changes are caught up on by re-reading the queue
+            while let Ok(_) | Err(RecvError::Lagged(_)) = changes.recv().await {
+                revision += 1;
+            }
+        }
+    });
+
+    revision
+}
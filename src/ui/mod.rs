@@ -0,0 +1,89 @@
+//! Screens of the desktop app and the state they share.
+mod error;
+mod format;
+mod history;
+mod hooks;
+mod nav;
+mod queue;
+mod recovery;
+mod settings;
+mod stats;
+mod trash;
+mod workspace;
+
+pub use history::History;
+pub use nav::NavBar;
+pub use recovery::{OpenFailure, Recovery};
+pub use settings::Settings;
+pub use stats::Stats;
+pub use trash::Trash;
+pub use workspace::Workspace;
+
+use std::{path::PathBuf, time::Duration};
+
+use sqlx::Sqlite;
+use yd_gui::{
+    database::{location::DbLocation, Database, PoolOptions},
+    download::{queue::DownloadQueue, DownloadManager},
+    metadata::Extractor,
+    selector::FormatSelector,
+    settings::Settings,
+    Result,
+};
+
+/// How many downloads the queue runs at the same time.
+const MAX_CONCURRENT_DOWNLOADS: usize = 3;
+
+/// How long deleted videos stay in the trash before being purged at startup.
+const TRASH_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// State shared by every screen through the Dioxus context.
+/// Retrieve it with `use_context::<AppState>()`.
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Database<Sqlite>,
+    /// Where the history database was opened from
+    pub db_location: DbLocation,
+    pub downloads: DownloadManager,
+    pub queue: DownloadQueue,
+    pub extractor: Extractor,
+    /// Picks the format preselected for the pasted URLs
+    pub format_selector: FormatSelector,
+    /// Where downloaded videos are saved
+    pub download_dir: PathBuf,
+}
+
+impl AppState {
+    /// Open the history database at `db_location`, empty the trash of the old
+    /// videos and restore the downloads left unfinished.
+    pub async fn init(db_location: DbLocation) -> Result<Self> {
+        let mut options = PoolOptions::default();
+        if let Some(max_connections) = Settings::load_or_default().db_max_connections {
+            options.max_connections = max_connections;
+        }
+        let db = Database::init_at(&db_location, options).await?;
+        db.purge_trash(TRASH_RETENTION).await?;
+        let downloads = DownloadManager::new();
+        let queue = DownloadQueue::new(db.clone(), downloads.clone(), MAX_CONCURRENT_DOWNLOADS);
+        queue.restore().await?;
+
+        Ok(Self {
+            db,
+            db_location,
+            downloads,
+            queue,
+            extractor: Extractor::new(),
+            format_selector: FormatSelector::best(),
+            download_dir: default_download_dir(),
+        })
+    }
+}
+
+/// The user's `Downloads` directory, or the working directory if there is
+/// no home directory.
+fn default_download_dir() -> PathBuf {
+    match std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+        Some(home) => PathBuf::from(home).join("Downloads"),
+        None => PathBuf::from("."),
+    }
+}
@@ -0,0 +1,20 @@
+use dioxus::prelude::*;
+
+use crate::Route;
+
+/// Layout wrapping every screen with the navigation bar.
+#[component]
+pub fn NavBar() -> Element {
+    rsx! {
+        nav { class: "flex gap-4 px-4 py-2 border-b",
+            Link { to: Route::Workspace {}, "Add" }
+            Link { to: Route::History {}, "History" }
+            Link { to: Route::Trash {}, "Trash" }
+            Link { to: Route::Stats {}, "Stats" }
+            Link { to: Route::Settings {}, "Settings" }
+        }
+        main { class: "p-4",
+            Outlet::<Route> {}
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use dioxus::prelude::*;
+use yd_gui::download::{progress::ProgressEvent, queue::DownloadState};
+
+use super::{
+    format::{format_bytes, format_duration},
+    hooks::{use_download_progress, use_queue_revision},
+    AppState,
+};
+
+/// List of the downloads in the queue with their live progress.
+#[component]
+pub fn DownloadList() -> Element {
+    let state = use_context::<AppState>();
+    let progress = use_download_progress();
+    let revision = use_queue_revision();
+
+    // Re-read whenever the queue changes
+    let _ = revision.read();
+    let entries = state.queue.entries();
+
+    let toggle = move |(id, pause): (i32, bool)| {
+        let queue = state.queue.clone();
+        spawn(async move {
+            let result = if pause {
+                queue.pause(id).await
+            } else {
+                queue.resume(id).await
+            };
+            if let Err(e) = result {
+                tracing::error!("failed to update download {id}: {e}");
+            }
+        });
+    };
+
+    rsx! {
+        ul {
+            for entry in entries {
+                li { key: "{entry.id}", class: "py-1",
+                    div { class: "flex justify-between",
+                        p { class: "font-semibold", "{entry.video.get_info().title}" }
+                        match entry.state {
+                            DownloadState::Queued | DownloadState::Downloading => rsx! {
+                                button {
+                                    onclick: {
+                                        let toggle = toggle.clone();
+                                        move |_| toggle((entry.id, true))
+                                    },
+                                    "Pause"
+                                }
+                            },
+                            DownloadState::Paused | DownloadState::Failed(_) => rsx! {
+                                button {
+                                    onclick: {
+                                        let toggle = toggle.clone();
+                                        move |_| toggle((entry.id, false))
+                                    },
+                                    "Resume"
+                                }
+                            },
+                            DownloadState::Done => None,
+                        }
+                    }
+                    DownloadProgress {
+                        state: entry.state.clone(),
+                        event: progress.read().get(&entry.id).copied(),
+                        resume_offset: entry.resume_offset,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn DownloadProgress(
+    state: DownloadState,
+    event: Option<ProgressEvent>,
+    resume_offset: u64,
+) -> Element {
+    let label = match (&state, event) {
+        // The last event may arrive before the queue marks the entry as done
+        (_, Some(event)) if event.fraction() == Some(1.0) => "Done".to_string(),
+        (DownloadState::Downloading, Some(event)) => describe(&event),
+        (DownloadState::Failed(reason), _) => format!("Failed: {reason}"),
+        (DownloadState::Paused, _) if resume_offset > 0 => {
+            format!("Paused, {} downloaded", format_bytes(resume_offset))
+        }
+        (state, _) => format!("{state:?}"),
+    };
+    let percent = event.and_then(|event| event.fraction()).unwrap_or(0.0) * 100.0;
+
+    rsx! {
+        div { class: "w-full h-2 bg-gray-200 rounded",
+            div { class: "h-2 bg-blue-500 rounded", style: "width: {percent}%" }
+        }
+        p { class: "text-sm", "{label}" }
+    }
+}
+
+fn describe(event: &ProgressEvent) -> String {
+    let downloaded = format_bytes(event.downloaded);
+    let speed = format_bytes(event.bytes_per_sec);
+    match (event.total, event.eta()) {
+        (Some(total), Some(eta)) => format!(
+            "{downloaded} of {} at {speed}/s, {} left",
+            format_bytes(total),
+            format_duration(eta)
+        ),
+        (Some(total), None) => format!("{downloaded} of {} at {speed}/s", format_bytes(total)),
+        (None, _) => format!("{downloaded} at {speed}/s"),
+    }
+}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+use sqlx::Sqlite;
+use yd_gui::database::{
+    location::{DbLocation, DB_ENV_VAR},
+    Database,
+};
+
+/// Why the app started on the [Recovery] screen, provided through the
+/// Dioxus context in place of the [AppState](super::AppState).
+#[derive(Clone)]
+pub struct OpenFailure {
+    /// `None` when it couldn't be found out where the database is
+    pub location: Option<DbLocation>,
+    pub error: String,
+}
+
+/// Screen shown instead of the app when the history database can't be
+/// opened, offering to restore its latest backup or to start over.
+#[component]
+pub fn Recovery() -> Element {
+    let failure = use_context::<OpenFailure>();
+    match failure.location {
+        Some(location) => rsx! {
+            RecoverAt { path: location.path, error: failure.error }
+        },
+        None => rsx! {
+            main { class: "p-4 flex flex-col gap-2",
+                p { "The history database couldn't be located:" }
+                p { class: "text-red-600", "{failure.error}" }
+                p { "Pass its path with --db, or set the {DB_ENV_VAR} environment variable" }
+            }
+        },
+    }
+}
+
+/// The [Recovery] of the database at `path`, which failed to open with `error`.
+#[component]
+fn RecoverAt(path: PathBuf, error: String) -> Element {
+    let mut status = use_signal(|| None::<String>);
+    let mut done = use_signal(|| false);
+
+    let backup = Database::<Sqlite>::latest_backup(&path);
+
+    let restore = {
+        let (path, backup) = (path.clone(), backup.clone());
+        move |_| {
+            let Some(backup) = &backup else {
+                return;
+            };
+            match Database::<Sqlite>::restore_backup(backup, &path) {
+                Ok(_) => {
+                    status.set(Some("Restored, restart yd-gui to continue".to_string()));
+                    done.set(true);
+                }
+                Err(e) => status.set(Some(format!("Failed to restore: {e}"))),
+            }
+        }
+    };
+
+    let start_over = {
+        let path = path.clone();
+        move |_| match Database::<Sqlite>::set_aside(&path) {
+            Ok(_) => {
+                status.set(Some(
+                    "The history will start empty, restart yd-gui to continue".to_string(),
+                ));
+                done.set(true);
+            }
+            Err(e) => status.set(Some(format!("Failed to move the database: {e}"))),
+        }
+    };
+
+    rsx! {
+        main { class: "p-4 flex flex-col gap-2",
+            p { "The history database at {path.display()} couldn't be opened:" }
+            p { class: "text-red-600", "{error}" }
+            if !done() {
+                if let Some(backup) = &backup {
+                    button { class: "border rounded px-4 self-start",
+                        onclick: restore,
+                        "Restore the backup {backup.display()}"
+                    }
+                }
+                button { class: "border rounded px-4 self-start",
+                    onclick: start_over,
+                    "Start with an empty history"
+                }
+                p { class: "text-sm",
+                    "The database is kept next to its location, renamed to end with .broken"
+                }
+            }
+            if let Some(status) = status() {
+                p { "{status}" }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dioxus::prelude::*;
+use yd_gui::database::backup::IntegrityReport;
+
+use super::AppState;
+
+/// Screen with app wide settings and maintenance actions.
+#[component]
+pub fn Settings() -> Element {
+    let state = use_context::<AppState>();
+    let mut confirming = use_signal(|| false);
+    let mut status = use_signal(|| None::<String>);
+
+    let db_path = state.db_location.path.display().to_string();
+    let db_source = state.db_location.source.describe();
+
+    let db = state.db.clone();
+    let clear_history = move |_| {
+        let db = db.clone();
+        confirming.set(false);
+        spawn(async move {
+            match db.trash_all().await {
+                Ok(n) => status.set(Some(format!("Moved {n} videos to the trash"))),
+                Err(e) => status.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    // Written next to the downloads, like the exports
+    let db = state.db.clone();
+    let download_dir = state.download_dir.clone();
+    let back_up = move |_| {
+        let db = db.clone();
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = download_dir.join(format!("yd-gui-history-{seconds}.db"));
+        spawn(async move {
+            match db.backup_to(&path).await {
+                Ok(_) => status.set(Some(format!("Backed up to {}", path.display()))),
+                Err(e) => status.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let db = state.db.clone();
+    let check_integrity = move |_| {
+        let db = db.clone();
+        spawn(async move {
+            match db.check_integrity().await {
+                Ok(report) => status.set(Some(describe_integrity(&report))),
+                Err(e) => status.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    rsx! {
+        section { class: "flex flex-col gap-2",
+            p { "History database: {db_path}, set by {db_source}" }
+            if confirming() {
+                div { class: "flex gap-2",
+                    span { "Move every video of the history to the trash?" }
+                    button { onclick: clear_history, "Yes" }
+                    button { onclick: move |_| confirming.set(false), "No" }
+                }
+            } else {
+                button { class: "border rounded px-4 self-start",
+                    onclick: move |_| confirming.set(true),
+                    "Clear history"
+                }
+            }
+            div { class: "flex gap-2",
+                button { class: "border rounded px-4", onclick: back_up, "Back up now" }
+                button { class: "border rounded px-4", onclick: check_integrity, "Check integrity" }
+            }
+            if let Some(status) = status() {
+                p { "{status}" }
+            }
+        }
+    }
+}
+
+/// e.g. `The history database has 2 problems: …`
+fn describe_integrity(report: &IntegrityReport) -> String {
+    if report.is_ok() {
+        return "The history database is fine".to_string();
+    }
+
+    let mut description = format!(
+        "The history database has {} problems:",
+        report.problems.len() + report.foreign_key_violations.len()
+    );
+    for problem in &report.problems {
+        description.push_str(&format!(" {problem}."));
+    }
+    for violation in &report.foreign_key_violations {
+        description.push_str(&format!(
+            " A row of {} references a missing row of {}.",
+            violation.table, violation.parent
+        ));
+    }
+    description
+}
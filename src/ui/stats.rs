@@ -0,0 +1,132 @@
+use std::time::{Duration, SystemTime};
+
+use dioxus::prelude::*;
+use yd_gui::database::stats::LibraryStats;
+
+use super::{
+    format::{format_bytes, format_duration},
+    AppState,
+};
+
+/// Seconds in a day.
+const DAY: u64 = 24 * 60 * 60;
+
+/// The periods the statistics can be computed over, `None` for all time.
+const RANGES: [(&str, Option<Duration>); 4] = [
+    ("All time", None),
+    ("Last year", Some(Duration::from_secs(365 * DAY))),
+    ("Last 30 days", Some(Duration::from_secs(30 * DAY))),
+    ("Last 7 days", Some(Duration::from_secs(7 * DAY))),
+];
+
+/// Screen with aggregate numbers about the history, over a chosen period.
+#[component]
+pub fn Stats() -> Element {
+    let state = use_context::<AppState>();
+    let mut range = use_signal(|| 0usize);
+
+    let db = state.db.clone();
+    let stats = use_resource(move || {
+        let db = db.clone();
+        let (_, period) = RANGES[range()];
+        async move {
+            match period.and_then(|period| SystemTime::now().checked_sub(period)) {
+                Some(since) => db.stats(since..).await,
+                None => db.stats(..).await,
+            }
+        }
+    });
+
+    rsx! {
+        select {
+            onchange: move |event| range.set(event.value().parse().unwrap_or_default()),
+            for (i, (name, _)) in RANGES.iter().enumerate() {
+                option { value: "{i}", selected: i == range(), "{name}" }
+            }
+        }
+        match &*stats.read() {
+            Some(Ok(stats)) => rsx! { StatsView { stats: stats.clone() } },
+            Some(Err(e)) => rsx! { p { class: "text-red-600", "{e}" } },
+            None => rsx! { p { "Loading…" } },
+        }
+    }
+}
+
+#[component]
+fn StatsView(stats: LibraryStats) -> Element {
+    let (videos, downloads) = (stats.videos, stats.downloads);
+    let bytes = format_bytes(stats.bytes_downloaded);
+    let duration = format_duration(stats.duration_downloaded);
+    let resolutions: Vec<(String, u64)> = stats
+        .resolutions
+        .iter()
+        .map(|&(height, count)| match height {
+            Some(height) => (format!("{height}p"), count),
+            None => ("Audio only".to_string(), count),
+        })
+        .collect();
+    // The busiest day fills the whole width
+    let busiest = stats
+        .downloads_per_day
+        .iter()
+        .map(|&(_, count)| count)
+        .max()
+        .unwrap_or(1);
+    let days: Vec<(String, u64, u64)> = stats
+        .downloads_per_day
+        .iter()
+        .map(|(day, count)| (day.clone(), *count, count * 100 / busiest))
+        .collect();
+
+    rsx! {
+        dl { class: "grid grid-cols-2 gap-x-4 my-4 w-fit",
+            dt { "Videos added" }
+            dd { "{videos}" }
+            dt { "Videos downloaded" }
+            dd { "{downloads}" }
+            dt { "Downloaded size" }
+            dd { "{bytes}" }
+            dt { "Downloaded length" }
+            dd { "{duration}" }
+        }
+        div { class: "flex gap-8",
+            Tally { title: "Top authors", rows: stats.top_authors.clone() }
+            Tally { title: "Containers", rows: stats.containers.clone() }
+            Tally { title: "Resolutions", rows: resolutions }
+        }
+        section { class: "my-4",
+            h2 { class: "font-semibold", "Downloads per day" }
+            if days.is_empty() {
+                p { "Nothing was downloaded" }
+            }
+            for (day, count, percent) in days.into_iter() {
+                div { key: "{day}", class: "flex gap-2 items-center",
+                    span { class: "w-24 text-sm", "{day}" }
+                    div { class: "h-3 bg-blue-500", style: "width: {percent}%" }
+                    span { class: "text-sm", "{count}" }
+                }
+            }
+        }
+    }
+}
+
+/// A table of how many videos there are of each kind.
+#[component]
+fn Tally(title: &'static str, rows: Vec<(String, u64)>) -> Element {
+    rsx! {
+        section {
+            h2 { class: "font-semibold", "{title}" }
+            if rows.is_empty() {
+                p { "None" }
+            }
+            table {
+                for (name, count) in rows.iter() {
+                    tr { key: "{name}",
+                        td { class: "pr-4", "{name}" }
+                        td { class: "text-right", "{count}" }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use dioxus::prelude::*;
+use yd_gui::database::{DeleteMode, DeleteReport};
+
+use super::{format::format_duration, AppState};
+
+/// What can be done with the downloaded file of a video deleted for good.
+const DELETE_MODES: [(&str, DeleteMode); 3] = [
+    ("Keep the downloaded file", DeleteMode::RowOnly),
+    (
+        "Move the downloaded file to the trash",
+        DeleteMode::FileToSystemTrash,
+    ),
+    ("Delete the downloaded file", DeleteMode::RowAndFile),
+];
+
+/// Screen listing the videos deleted from the history, most recently deleted
+/// first, which can be restored or deleted for good.
+#[component]
+pub fn Trash() -> Element {
+    let state = use_context::<AppState>();
+    let mut error = use_signal(|| None::<String>);
+    let mut delete_mode = use_signal(|| 0usize);
+
+    let db = state.db.clone();
+    let mut videos = use_resource(move || {
+        let db = db.clone();
+        async move { db.fetch_trash().await }
+    });
+
+    let db = state.db.clone();
+    let restore = move |id: i32| {
+        let db = db.clone();
+        spawn(async move {
+            match db.restore(id).await {
+                Ok(_) => videos.restart(),
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let db = state.db.clone();
+    let delete = move |id: i32| {
+        let db = db.clone();
+        let (_, mode) = DELETE_MODES[delete_mode()];
+        spawn(async move {
+            match db.delete_video_info(id, mode).await {
+                Ok(report) => {
+                    if let Some(e) = describe_file_error(&report) {
+                        error.set(Some(e));
+                    }
+                    videos.restart();
+                }
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let db = state.db.clone();
+    let empty = move |_| {
+        let db = db.clone();
+        spawn(async move {
+            match db.purge_trash(Duration::ZERO).await {
+                Ok(_) => videos.restart(),
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "flex gap-4",
+            button { class: "border rounded px-4", onclick: empty, "Empty trash" }
+            select {
+                onchange: move |event| delete_mode.set(event.value().parse().unwrap_or_default()),
+                for (i, (name, _)) in DELETE_MODES.iter().enumerate() {
+                    option { value: "{i}", selected: i == delete_mode(), "{name}" }
+                }
+            }
+        }
+        if let Some(e) = error() {
+            p { class: "text-red-600", "{e}" }
+        }
+        match &*videos.read() {
+            Some(Ok(videos)) if videos.is_empty() => rsx! { p { "The trash is empty" } },
+            Some(Ok(videos)) => rsx! {
+                ul {
+                    for video in videos.iter() {
+                        TrashRow {
+                            key: "{video.get_id()}",
+                            id: video.get_id(),
+                            title: video.get_info().title.clone(),
+                            author: video.get_info().author.clone(),
+                            duration: format_duration(video.get_info().duration),
+                            on_restore: restore.clone(),
+                            on_delete: delete.clone(),
+                        }
+                    }
+                }
+            },
+            Some(Err(e)) => rsx! { p { class: "text-red-600", "{e}" } },
+            None => rsx! { p { "Loading…" } },
+        }
+    }
+}
+
+#[component]
+fn TrashRow(
+    id: i32,
+    title: String,
+    author: String,
+    duration: String,
+    on_restore: EventHandler<i32>,
+    on_delete: EventHandler<i32>,
+) -> Element {
+    rsx! {
+        li { class: "flex justify-between py-1",
+            div {
+                p { class: "font-semibold", "{title}" }
+                p { class: "text-sm", "{author} · {duration}" }
+            }
+            div { class: "flex gap-2",
+                button { onclick: move |_| on_restore.call(id), "Restore" }
+                button { onclick: move |_| on_delete.call(id), "Delete" }
+            }
+        }
+    }
+}
+
+/// e.g. `Deleted from the history but not /videos/a.mp4: Permission denied`
+fn describe_file_error(report: &DeleteReport) -> Option<String> {
+    let (Some(path), Some(e)) = (&report.output_path, &report.file_error) else {
+        return None;
+    };
+    Some(format!(
+        "Deleted from the history but not {}: {e}",
+        path.display()
+    ))
+}
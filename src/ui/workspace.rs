@@ -0,0 +1,202 @@
+use dioxus::prelude::*;
+use yd_gui::{
+    download::file_name,
+    video::{parse_url, DownloadStatus, ManagedVideo, VideoFormat, VideoInfo, YouTubeUrl},
+};
+
+use super::{error::describe_error, format::format_bytes, queue::DownloadList, AppState};
+
+/// Screen where URLs are entered to be added to the downloads.
+#[component]
+pub fn Workspace() -> Element {
+    let mut url = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    let mut pending = use_signal(Vec::<YouTubeUrl>::new);
+
+    let mut add = move || {
+        let parsed = parse_url(&url.read());
+        match parsed {
+            Ok(parsed) => {
+                if !pending.read().iter().any(|p| p.video_id == parsed.video_id) {
+                    pending.write().push(parsed);
+                }
+                url.set(String::new());
+                error.set(None);
+            }
+            Err(e) => error.set(Some(e.to_string())),
+        }
+    };
+
+    rsx! {
+        form { class: "flex gap-2",
+            onsubmit: move |_| add(),
+            input { class: "flex-1 border rounded px-2",
+                placeholder: "Paste a YouTube URL",
+                value: "{url}",
+                oninput: move |event| url.set(event.value()),
+            }
+            button { class: "border rounded px-4", r#type: "submit", "Add" }
+        }
+        if let Some(e) = error() {
+            p { class: "text-red-600", "{e}" }
+        }
+        ul { class: "mt-4",
+            for entry in pending.read().iter() {
+                Candidate { key: "{entry.video_id}",
+                    url: entry.clone(),
+                    // By id since the candidates may change while downloading
+                    on_remove: {
+                        let video_id = entry.video_id.clone();
+                        move |_| pending.write().retain(|p| p.video_id != video_id)
+                    },
+                }
+            }
+        }
+        h2 { class: "mt-4 font-semibold", "Downloads" }
+        DownloadList {}
+    }
+}
+
+/// A pasted URL whose metadata is fetched with yt-dlp, to choose the format
+/// to download.
+#[component]
+fn Candidate(url: YouTubeUrl, on_remove: EventHandler<()>) -> Element {
+    let state = use_context::<AppState>();
+    // Index of the format picked by the user, if not the preselected one
+    let mut chosen = use_signal(|| None::<usize>);
+    let mut error = use_signal(|| None::<String>);
+
+    let extractor = state.extractor.clone();
+    let normalized = url.normalized();
+    let info = use_resource(move || {
+        let extractor = extractor.clone();
+        let url = normalized.clone();
+        async move { extractor.extract(&url).await }
+    });
+
+    // Where the video was saved if it was downloaded before
+    let db = state.db.clone();
+    let video_id = url.video_id.clone();
+    let previous = use_resource(move || {
+        let db = db.clone();
+        let video_id = video_id.clone();
+        async move {
+            let video = db
+                .find_by_video_id(video_id.as_str())
+                .await
+                .ok()
+                .flatten()?;
+            let record = video.get_record();
+            (record.status == DownloadStatus::Downloaded)
+                .then(|| record.output_path.clone())
+                .flatten()
+        }
+    });
+
+    let selector = state.format_selector.clone();
+    let download = move |(video_info, index): (VideoInfo, usize)| {
+        let state = state.clone();
+        let Some(format) = video_info.video_formats.get(index).cloned() else {
+            return;
+        };
+        spawn(async move {
+            let destination = state.download_dir.join(file_name(&video_info, &format));
+            let result = match state.db.upsert_video_info(&video_info).await {
+                Ok(id) => {
+                    let video = ManagedVideo::new(id, video_info);
+                    state.queue.push(video, format, destination).await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(_) => on_remove.call(()),
+                Err(e) => error.set(Some(describe_error(&e))),
+            }
+        });
+    };
+
+    rsx! {
+        li { class: "flex justify-between items-center py-1",
+            match &*info.read() {
+                None => rsx! { span { "Fetching {url.normalized()}…" } },
+                Some(Err(e)) => rsx! { span { class: "text-red-600", "{url.normalized()}: {e}" } },
+                Some(Ok(video_info)) => {
+                    let mut video_info = video_info.clone();
+                    // Best resolution first
+                    video_info.video_formats.sort_by(|a, b| {
+                        (b.height, b.width).cmp(&(a.height, a.width)).then(b.fps.total_cmp(&a.fps))
+                    });
+                    // A video can't be merged with its audio yet so only
+                    // the formats complete on their own are preselected
+                    let complete: Vec<VideoFormat> = video_info
+                        .video_formats
+                        .iter()
+                        .filter(|format| format.acodec.is_some())
+                        .cloned()
+                        .collect();
+                    let selection = selector.select(&complete);
+                    let preselected = selection
+                        .as_ref()
+                        .and_then(|selection| {
+                            let id = &selection.choice.video().format_id;
+                            video_info.video_formats.iter().position(|format| &format.format_id == id)
+                        })
+                        .unwrap_or_default();
+                    let index = chosen().unwrap_or(preselected);
+                    rsx! {
+                        div { class: "flex-1",
+                            p { "{video_info.title}" }
+                            if let Some(Some(path)) = &*previous.read() {
+                                p { class: "text-sm", "Already downloaded to {path.display()}" }
+                            }
+                            if let (Some(selection), None) = (&selection, chosen()) {
+                                p { class: "text-sm", "{selection.explanation}" }
+                            }
+                        }
+                        select {
+                            onchange: move |event| chosen.set(event.value().parse().ok()),
+                            for (i, format) in video_info.video_formats.iter().enumerate() {
+                                option { value: "{i}", selected: i == index,
+                                    "{describe(format)}"
+                                }
+                            }
+                        }
+                        button { class: "border rounded px-2",
+                            disabled: video_info.video_formats.is_empty(),
+                            onclick: move |_| download((video_info.clone(), index)),
+                            "Download"
+                        }
+                    }
+                }
+            }
+            if let Some(e) = error() {
+                span { class: "text-red-600", "{e}" }
+            }
+            button { onclick: move |_| on_remove.call(()), "Remove" }
+        }
+    }
+}
+
+/// e.g. `1920x1080 60fps HDR vp9 webm, 66.7 MiB`
+fn describe(format: &VideoFormat) -> String {
+    let mut description = if format.is_audio_only {
+        "Audio only".to_string()
+    } else {
+        let mut video = format!("{}x{} {}fps", format.width, format.height, format.fps);
+        if format.hdr {
+            video.push_str(" HDR");
+        }
+        video
+    };
+    for codec in [&format.vcodec, &format.acodec].into_iter().flatten() {
+        description.push(' ');
+        description.push_str(codec);
+    }
+    description.push(' ');
+    description.push_str(&format.container);
+    if let Some(filesize) = format.filesize {
+        description.push_str(", ");
+        description.push_str(&format_bytes(filesize));
+    }
+    description
+}
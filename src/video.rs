@@ -1,32 +1,159 @@
 //! Represents information related to a video
-use std::sync::{atomic::AtomicBool, Arc};
+use std::{
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
 
-#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+use serde::{Deserialize, Serialize};
+use tokio::sync::{futures::Notified, Notify};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VideoInfo {
     pub video_id: String,
     pub title: String,
     pub author: String,
-    pub duration_seconds: String,
+    /// Zero for live streams. Stored in whole seconds.
+    #[serde(rename = "duration_seconds", with = "duration_seconds")]
+    pub duration: Duration,
     pub thumbnail: Option<String>,
-    #[sqlx(skip)]
+    #[serde(default)]
     pub video_formats: Vec<VideoFormat>,
     pub audio_available: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+impl VideoInfo {
+    /// A video whose metadata is unknown yet, e.g. imported from a yt-dlp
+    /// download archive. Its title is empty.
+    pub fn placeholder(video_id: impl Into<String>) -> Self {
+        Self {
+            video_id: video_id.into(),
+            title: String::new(),
+            author: String::new(),
+            duration: Duration::ZERO,
+            thumbnail: None,
+            video_formats: Vec::new(),
+            audio_available: false,
+        }
+    }
+
+    /// Whether only the id of the video is known, see [placeholder](Self::placeholder).
+    pub fn is_placeholder(&self) -> bool {
+        self.title.is_empty()
+    }
+}
+
+/// Serializes a [Duration] as whole seconds.
+mod duration_seconds {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VideoFormat {
+    /// Identifier of the format among those of its video, e.g. yt-dlp's `"399"`
+    pub format_id: String,
     pub container: String,
-    pub width: String,
-    pub height: String,
-    pub fps: String,
+    /// Zero for audio only formats
+    pub width: u32,
+    /// Zero for audio only formats
+    pub height: u32,
+    /// Zero for audio only formats
+    pub fps: f32,
+    /// Video codec, e.g. `"av01.0.08M.08"`, `None` for audio only formats
+    pub vcodec: Option<String>,
+    /// Audio codec, e.g. `"opus"`, `None` for formats without audio
+    pub acodec: Option<String>,
+    /// Average bitrate of the audio and video together
+    pub bitrate_kbps: Option<u32>,
+    /// Size of the media in bytes, exact or approximated
+    pub filesize: Option<u64>,
+    /// Whether the video has a high dynamic range
+    pub hdr: bool,
+    pub is_audio_only: bool,
+    /// Direct link to the media. It is neither stored in the database nor
+    /// serialized because these links expire.
+    #[serde(skip)]
+    pub url: Option<String>,
+}
+
+/// Whether a video of the history was downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum DownloadStatus {
+    /// Added to the history but not downloaded (yet)
+    #[default]
+    Added,
+    Downloaded,
+    /// The last attempt to download it failed
+    Failed,
+}
+
+/// When and where a video of the history was downloaded, and when it was
+/// deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadRecord {
+    pub added_at: SystemTime,
+    pub downloaded_at: Option<SystemTime>,
+    /// The file the video was saved as
+    pub output_path: Option<PathBuf>,
+    /// Size in bytes of the [output_path](Self::output_path)
+    pub file_size: Option<u64>,
+    /// [format_id](VideoFormat::format_id) of the format that was downloaded
+    pub chosen_format_id: Option<String>,
+    pub status: DownloadStatus,
+    /// When the video was moved to the trash, `None` if it's not there
+    pub deleted_at: Option<SystemTime>,
+}
+
+impl Default for DownloadRecord {
+    /// A video just added
+    fn default() -> Self {
+        Self {
+            added_at: SystemTime::now(),
+            downloaded_at: None,
+            output_path: None,
+            file_size: None,
+            chosen_format_id: None,
+            status: DownloadStatus::default(),
+            deleted_at: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ManagedVideo {
     id: i32,
     video_info: VideoInfo,
-    content_size: Option<u64>,
-    downloading: Arc<AtomicBool>,
+    record: DownloadRecord,
+    download: Arc<DownloadState>,
+}
+
+/// The state of the download of a [ManagedVideo], shared by its clones.
+#[derive(Debug, Default)]
+struct DownloadState {
+    downloading: AtomicBool,
+    /// Set for good, so that a cancellation before the download has started
+    /// isn't lost
+    cancelled: AtomicBool,
+    content_size: Mutex<Option<u64>>,
+    /// Wakes up the download when it's cancelled
+    cancel_notify: Notify,
 }
 
 impl From<ManagedVideo> for VideoInfo {
@@ -40,12 +167,409 @@ impl ManagedVideo {
         Self {
             id,
             video_info,
-            content_size: None,
-            downloading: Arc::new(AtomicBool::new(false)),
+            record: DownloadRecord::default(),
+            download: Arc::default(),
         }
     }
 
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+
     pub fn get_info(&self) -> &VideoInfo {
         &self.video_info
     }
+
+    /// When and where the video was downloaded, as of when it was fetched
+    /// from the database.
+    pub fn get_record(&self) -> &DownloadRecord {
+        &self.record
+    }
+
+    pub(crate) fn with_record(mut self, record: DownloadRecord) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// Size in bytes of the media, known once its download has started.
+    /// Clones of a [ManagedVideo] share this state.
+    pub fn get_content_size(&self) -> Option<u64> {
+        *self.download.content_size.lock().unwrap()
+    }
+
+    pub(crate) fn set_content_size(&self, content_size: Option<u64>) {
+        *self.download.content_size.lock().unwrap() = content_size;
+    }
+
+    /// Whether the video is being downloaded. Clones of a [ManagedVideo]
+    /// share this state.
+    pub fn is_downloading(&self) -> bool {
+        self.download.downloading.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_downloading(&self, downloading: bool) {
+        self.download
+            .downloading
+            .store(downloading, Ordering::Release);
+    }
+
+    /// Ask an ongoing download of the video to stop, or a download about to
+    /// start not to. It can be called on any clone of the [ManagedVideo].
+    ///
+    /// The clones can't be downloaded anymore afterwards, download a
+    /// [fresh](Self::with_fresh_download) one instead.
+    pub fn cancel_download(&self) {
+        self.download.cancelled.store(true, Ordering::SeqCst);
+        self.set_downloading(false);
+        self.download.cancel_notify.notify_waiters();
+    }
+
+    /// Whether [cancel_download](Self::cancel_download) was called on a clone
+    /// sharing the download state.
+    pub fn is_cancelled(&self) -> bool {
+        self.download.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes when [cancel_download](Self::cancel_download) is called
+    /// after this is [enabled](Notified::enable).
+    pub(crate) fn cancelled(&self) -> Notified<'_> {
+        self.download.cancel_notify.notified()
+    }
+
+    /// A clone that doesn't share the download state, to download the video
+    /// again after a [cancellation](Self::cancel_download).
+    pub fn with_fresh_download(&self) -> Self {
+        Self {
+            download: Arc::default(),
+            ..self.clone()
+        }
+    }
+}
+
+/// A YouTube video id, 11 characters among `A-Z`, `a-z`, `0-9`, `-` and `_`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VideoId(String);
+
+impl VideoId {
+    const LEN: usize = 11;
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for VideoId {
+    type Err = UrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == Self::LEN && s.bytes().all(is_id_byte) {
+            Ok(Self(s.to_string()))
+        } else {
+            Err(UrlError::InvalidVideoId(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for VideoId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<VideoId> for String {
+    fn from(value: VideoId) -> Self {
+        value.0
+    }
+}
+
+fn is_id_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'
+}
+
+/// The ways user input can fail to be [parsed](parse_url) as a YouTube URL.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum UrlError {
+    #[error("not a YouTube URL")]
+    NotYouTube,
+    #[error("the URL doesn't point to a video")]
+    MissingVideoId,
+    #[error("\"{0}\" is not a valid video id")]
+    InvalidVideoId(String),
+}
+
+/// What a YouTube URL points to, see [parse_url].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YouTubeUrl {
+    pub video_id: VideoId,
+    /// Id of the playlist the video was opened from
+    pub playlist_id: Option<String>,
+    /// Where playback starts, from the `t` or `start` parameter
+    pub start: Option<Duration>,
+}
+
+impl YouTubeUrl {
+    /// The canonical `watch?v=` URL of the video, ignoring the playlist and
+    /// start time.
+    pub fn normalized(&self) -> String {
+        watch_url(&self.video_id)
+    }
+}
+
+/// The canonical `watch?v=` URL of the video with the matching `video_id`.
+pub fn watch_url(video_id: impl fmt::Display) -> String {
+    format!("https://www.youtube.com/watch?v={video_id}")
+}
+
+/// Parse the URL of a YouTube video as pasted by a user.
+///
+/// Accepts the `watch?v=`, `youtu.be/`, `/shorts/`, `/embed/`, `/live/` and
+/// `/v/` shapes on the `www.`, `m.` and `music.` hosts, with or without a
+/// scheme. A bare video id is accepted too. Unrelated query parameters are
+/// ignored.
+///
+/// # Example
+/// ```
+/// # use yd_gui::video::parse_url;
+/// # use std::time::Duration;
+/// let url = parse_url("https://youtu.be/dQw4w9WgXcQ?t=1m30s").unwrap();
+///
+/// assert_eq!(url.video_id.as_str(), "dQw4w9WgXcQ");
+/// assert_eq!(url.start, Some(Duration::from_secs(90)));
+/// assert_eq!(url.normalized(), "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+/// ```
+pub fn parse_url(input: &str) -> Result<YouTubeUrl, UrlError> {
+    let input = input.trim();
+    if let Ok(video_id) = input.parse() {
+        return Ok(YouTubeUrl {
+            video_id,
+            playlist_id: None,
+            start: None,
+        });
+    }
+
+    let without_scheme = ["https://", "http://"]
+        .iter()
+        .find_map(|scheme| strip_prefix_ignore_case(input, scheme))
+        .unwrap_or(input);
+    let (without_fragment, fragment) = without_scheme
+        .split_once('#')
+        .unwrap_or((without_scheme, ""));
+    let (authority_and_path, query) = without_fragment
+        .split_once('?')
+        .unwrap_or((without_fragment, ""));
+    let (host, path) = authority_and_path
+        .split_once('/')
+        .unwrap_or((authority_and_path, ""));
+
+    let host = host.to_ascii_lowercase();
+    let host = host.split(':').next().unwrap_or_default();
+    let host = ["www.", "m.", "music."]
+        .iter()
+        .find_map(|prefix| host.strip_prefix(prefix))
+        .unwrap_or(host);
+
+    let params: Vec<(&str, &str)> = query
+        .split('&')
+        .chain(fragment.split('&'))
+        .filter_map(|param| param.split_once('='))
+        .collect();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, value)| *key == name && !value.is_empty())
+            .map(|(_, value)| *value)
+    };
+
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    let video_id = match host {
+        "youtu.be" => segments.next(),
+        "youtube.com" | "youtube-nocookie.com" => match segments.next() {
+            Some("watch") => param("v"),
+            Some("shorts" | "embed" | "live" | "v") => segments.next(),
+            _ => None,
+        },
+        _ => return Err(UrlError::NotYouTube),
+    };
+
+    Ok(YouTubeUrl {
+        video_id: video_id.ok_or(UrlError::MissingVideoId)?.parse()?,
+        playlist_id: param("list")
+            .filter(|list| list.bytes().all(is_id_byte))
+            .map(str::to_string),
+        start: param("t")
+            .or_else(|| param("start"))
+            .and_then(parse_timestamp),
+    })
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+/// Parse a timestamp like `90`, `90s`, `1m30s` or `1h2m3s`, `None` if it's
+/// malformed or too large.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    if let Ok(secs) = timestamp.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let mut secs = 0;
+    let mut number = String::new();
+    for c in timestamp.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let part = number.parse::<u64>().ok()?.checked_mul(unit)?;
+        secs = part.checked_add(secs)?;
+        number.clear();
+    }
+
+    number.is_empty().then_some(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_url, UrlError, VideoId};
+
+    const ID: &str = "dQw4w9WgXcQ";
+
+    #[test]
+    fn video_id() {
+        assert_eq!(ID.parse::<VideoId>().unwrap().as_str(), ID);
+        assert_eq!(
+            "a-b_c123XYZ".parse::<VideoId>().unwrap().as_str(),
+            "a-b_c123XYZ"
+        );
+
+        for invalid in [
+            "",
+            "dQw4w9WgXc",
+            "dQw4w9WgXcQQ",
+            "dQw4w9WgXc!",
+            "dQw4w9WgXc ",
+        ] {
+            assert_eq!(
+                invalid.parse::<VideoId>(),
+                Err(UrlError::InvalidVideoId(invalid.to_string())),
+                "{invalid:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_valid_urls() {
+        // (input, playlist id, start in seconds)
+        let cases: &[(&str, Option<&str>, Option<u64>)] = &[
+            ("dQw4w9WgXcQ", None, None),
+            ("  dQw4w9WgXcQ\n", None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ", None, None),
+            ("http://www.youtube.com/watch?v=dQw4w9WgXcQ", None, None),
+            ("HTTPS://WWW.YOUTUBE.COM/watch?v=dQw4w9WgXcQ", None, None),
+            ("www.youtube.com/watch?v=dQw4w9WgXcQ", None, None),
+            ("youtube.com/watch?v=dQw4w9WgXcQ", None, None),
+            ("https://m.youtube.com/watch?v=dQw4w9WgXcQ", None, None),
+            ("https://music.youtube.com/watch?v=dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com:443/watch?v=dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com/watch/?v=dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com/watch?feature=share&v=dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42", None, Some(42)),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42s", None, Some(42)),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1m30s", None, Some(90)),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1h2m3s", None, Some(3723)),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ#t=2m", None, Some(120)),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=bogus", None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=99999999999999999999h", None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=9999999999999999h", None, None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=18446744073709551615s1s", None, None),
+            (
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG&index=3",
+                Some("PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"),
+                None,
+            ),
+            (
+                "https://www.youtube.com/watch?list=RDdQw4w9WgXcQ&v=dQw4w9WgXcQ&t=5",
+                Some("RDdQw4w9WgXcQ"),
+                Some(5),
+            ),
+            ("https://youtu.be/dQw4w9WgXcQ", None, None),
+            ("youtu.be/dQw4w9WgXcQ", None, None),
+            ("https://youtu.be/dQw4w9WgXcQ?si=abcdEFGH1234", None, None),
+            ("https://youtu.be/dQw4w9WgXcQ?t=10", None, Some(10)),
+            ("https://youtu.be/dQw4w9WgXcQ?list=PL123", Some("PL123"), None),
+            ("https://www.youtube.com/shorts/dQw4w9WgXcQ", None, None),
+            ("https://youtube.com/shorts/dQw4w9WgXcQ?feature=share", None, None),
+            ("https://m.youtube.com/shorts/dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com/embed/dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com/embed/dQw4w9WgXcQ?start=30", None, Some(30)),
+            ("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com/live/dQw4w9WgXcQ", None, None),
+            ("https://www.youtube.com/live/dQw4w9WgXcQ?feature=shared", None, None),
+            ("https://www.youtube.com/v/dQw4w9WgXcQ", None, None),
+        ];
+
+        for (input, playlist_id, start) in cases {
+            let url = parse_url(input).unwrap_or_else(|e| panic!("{input:?}: {e}"));
+            assert_eq!(url.video_id.as_str(), ID, "{input:?}");
+            assert_eq!(url.playlist_id.as_deref(), *playlist_id, "{input:?}");
+            assert_eq!(url.start, start.map(Duration::from_secs), "{input:?}");
+            assert_eq!(
+                url.normalized(),
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_invalid_urls() {
+        let cases: &[(&str, UrlError)] = &[
+            ("", UrlError::NotYouTube),
+            ("https://vimeo.com/123456", UrlError::NotYouTube),
+            (
+                "https://notyoutube.com/watch?v=dQw4w9WgXcQ",
+                UrlError::NotYouTube,
+            ),
+            (
+                "https://youtube.com.evil.com/watch?v=dQw4w9WgXcQ",
+                UrlError::NotYouTube,
+            ),
+            ("https://www.youtube.com/", UrlError::MissingVideoId),
+            ("https://www.youtube.com/watch", UrlError::MissingVideoId),
+            ("https://www.youtube.com/watch?v=", UrlError::MissingVideoId),
+            (
+                "https://www.youtube.com/playlist?list=PL123",
+                UrlError::MissingVideoId,
+            ),
+            ("https://www.youtube.com/@channel", UrlError::MissingVideoId),
+            ("https://youtu.be/", UrlError::MissingVideoId),
+            ("https://www.youtube.com/shorts/", UrlError::MissingVideoId),
+            (
+                "https://www.youtube.com/watch?v=short",
+                UrlError::InvalidVideoId("short".to_string()),
+            ),
+            (
+                "https://youtu.be/dQw4w9WgXcQQ",
+                UrlError::InvalidVideoId("dQw4w9WgXcQQ".to_string()),
+            ),
+            (
+                "https://www.youtube.com/embed/dQw4w9WgXc%21",
+                UrlError::InvalidVideoId("dQw4w9WgXc%21".to_string()),
+            ),
+        ];
+
+        for (input, error) in cases {
+            assert_eq!(parse_url(input).as_ref(), Err(error), "{input:?}");
+        }
+    }
 }